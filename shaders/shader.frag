@@ -2,8 +2,58 @@
 
 layout (location=0) out vec4 theColour;
 layout (location=0) in vec4 data_from_the_vertexshader;
+layout (location=1) in vec3 worldposition;
+layout (location=2) in vec3 worldnormal;
 
+layout (set=0, binding=0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+    vec4 camera_position;
+} ubo;
+
+struct Light {
+    vec4 position; //w=0: xyz is the direction a directional light travels, w=1: position of a point light
+    vec4 colour;
+};
+//has to match Lighting::MAX_LIGHTS
+const uint MAX_LIGHTS = 16;
+layout (set=0, binding=1) uniform LightBlock {
+    vec4 ambient;
+    uint amount;
+    Light lights[MAX_LIGHTS];
+} lighting;
+
+const float SHININESS = 32.0;
+const float SPECULAR_STRENGTH = 0.5;
 
 void main(){
-    theColour= data_from_the_vertexshader;
+    //meshes without normals stay unlit
+    if (dot(worldnormal, worldnormal) < 1e-12) {
+        theColour = data_from_the_vertexshader;
+        return;
+    }
+    vec3 albedo = data_from_the_vertexshader.rgb;
+    vec3 n = normalize(worldnormal);
+    vec3 v = normalize(ubo.camera_position.xyz - worldposition);
+    vec3 result = lighting.ambient.rgb*albedo;
+    for (uint i = 0; i < min(lighting.amount, MAX_LIGHTS); i++) {
+        Light light = lighting.lights[i];
+        vec3 l;
+        float attenuation;
+        if (light.position.w == 0.0) {
+            l = normalize(-light.position.xyz);
+            attenuation = 1.0;
+        } else {
+            vec3 to_light = light.position.xyz - worldposition;
+            float distance_squared = dot(to_light, to_light);
+            l = to_light*inversesqrt(distance_squared);
+            attenuation = 1.0/(1.0 + distance_squared);
+        }
+        //Lambert for the diffuse part, Blinn-Phong for the highlight
+        float diffuse = max(dot(n, l), 0.0);
+        vec3 h = normalize(l + v);
+        float specular = diffuse > 0.0 ? pow(max(dot(n, h), 0.0), SHININESS) : 0.0;
+        result += attenuation*light.colour.rgb*(diffuse*albedo + SPECULAR_STRENGTH*specular);
+    }
+    theColour = vec4(result, data_from_the_vertexshader.a);
 }
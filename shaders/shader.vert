@@ -1,18 +1,32 @@
 #version 450
 
 layout (location=0) in vec3 position;
-layout (location=1) in mat4 model_matrix;
-layout (location=5) in vec3 colour;
+layout (location=1) in vec3 normal;
+//the columns of the model matrix
+layout (location=2) in vec4 model_column0;
+layout (location=3) in vec4 model_column1;
+layout (location=4) in vec4 model_column2;
+layout (location=5) in vec4 model_column3;
+layout (location=6) in vec3 colour;
 
 layout (set=0, binding=0) uniform UniformBufferObject {
     mat4 view_matrix;
     mat4 projection_matrix;
+    vec4 camera_position;
 } ubo;
 
 layout (location=0) out vec4 colourdata_for_the_fragmentshader;
+layout (location=1) out vec3 worldposition;
+layout (location=2) out vec3 worldnormal;
 
 void main() {
-    //normal = cross(position.y-position.x, position.z-position.x); //of triangle
-    gl_Position = ubo.projection_matrix*ubo.view_matrix*model_matrix*vec4(position,1.0);
+    mat4 model_matrix = mat4(model_column0,model_column1,model_column2,model_column3);
+    vec4 worldposition4 = model_matrix*vec4(position,1.0);
+    //only used when drawing points, and only if the device has largePoints
+    gl_PointSize = 3.0;
+    gl_Position = ubo.projection_matrix*ubo.view_matrix*worldposition4;
+    worldposition = worldposition4.xyz;
+    //the inverse transpose keeps normals perpendicular to surfaces under non-uniform scaling
+    worldnormal = transpose(inverse(mat3(model_matrix)))*normal;
     colourdata_for_the_fragmentshader = vec4(colour,1.0);
 }
@@ -0,0 +1,25 @@
+#version 450
+
+layout (location=0) in vec3 position;
+layout (location=1) in vec3 colour;
+//the columns of the model matrix
+layout (location=2) in vec4 model_column0;
+layout (location=3) in vec4 model_column1;
+layout (location=4) in vec4 model_column2;
+layout (location=5) in vec4 model_column3;
+
+layout (set=0, binding=0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+    vec4 camera_position;
+} ubo;
+
+layout (location=0) out vec4 colourdata_for_the_fragmentshader;
+
+void main() {
+    mat4 model_matrix = mat4(model_column0,model_column1,model_column2,model_column3);
+    //only used when drawing points, and only if the device has largePoints
+    gl_PointSize = 3.0;
+    gl_Position = ubo.projection_matrix*ubo.view_matrix*model_matrix*vec4(position,1.0);
+    colourdata_for_the_fragmentshader = vec4(colour,1.0);
+}
@@ -1,10 +1,59 @@
 use ash::vk;
+use bytemuck::Pod;
 use vk_mem::Alloc;
 
+#[derive(Debug)]
+pub(crate) enum BufferError {
+    Vulkan(vk::Result),
+    OutOfBounds { requested: u64, capacity: u64 },
+    NotHostVisible,
+}
+
+impl From<vk::Result> for BufferError {
+    fn from(e: vk::Result) -> Self {
+        BufferError::Vulkan(e)
+    }
+}
+
+impl std::fmt::Display for BufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BufferError::Vulkan(e) => write!(f, "buffer operation failed: {}", e),
+            BufferError::OutOfBounds {
+                requested,
+                capacity,
+            } => write!(
+                f,
+                "writing {} bytes into a buffer of {} bytes",
+                requested, capacity
+            ),
+            BufferError::NotHostVisible => {
+                write!(f, "the buffer's memory is not visible to the CPU")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BufferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BufferError::Vulkan(e) => Some(e),
+            BufferError::OutOfBounds { .. } | BufferError::NotHostVisible => None,
+        }
+    }
+}
+
 pub struct Buffer {
     pub(crate) buffer: vk::Buffer,
     allocation: vk_mem::Allocation,
     //allocation_info: vk_mem::AllocationInfo,
+    usage: vk::BufferUsageFlags,
+    memory_usage: vk_mem::MemoryUsage,
+    //null unless the memory is host-visible, in which case it stays mapped until the buffer is
+    //destroyed
+    mapped: *mut u8,
+    //otherwise writes have to be flushed before the GPU sees them
+    coherent: bool,
 }
 
 impl Buffer {
@@ -14,8 +63,19 @@ impl Buffer {
         usage: vk::BufferUsageFlags,
         memory_usage: vk_mem::MemoryUsage,
     ) -> Result<Buffer, vk::Result> {
+        let host_visible = matches!(
+            memory_usage,
+            vk_mem::MemoryUsage::CpuOnly
+                | vk_mem::MemoryUsage::CpuToGpu
+                | vk_mem::MemoryUsage::GpuToCpu
+        );
         let allocation_create_info = vk_mem::AllocationCreateInfo {
             usage: memory_usage,
+            flags: if host_visible {
+                vk_mem::AllocationCreateFlags::MAPPED
+            } else {
+                vk_mem::AllocationCreateFlags::empty()
+            },
             ..Default::default()
         };
         let (buffer, allocation) = unsafe {
@@ -27,20 +87,180 @@ impl Buffer {
                 &allocation_create_info,
             )?
         };
+        let allocation_info = allocator.get_allocation_info(&allocation);
+        let memory_type =
+            allocator.get_memory_properties().memory_types[allocation_info.memory_type as usize];
         Ok(Buffer {
             buffer,
             allocation,
             //allocation_info,
+            usage,
+            memory_usage,
+            mapped: allocation_info.mapped_data as *mut u8,
+            coherent: memory_type
+                .property_flags
+                .contains(vk::MemoryPropertyFlags::HOST_COHERENT),
         })
     }
-    pub(crate) unsafe fn fill<T: Sized>(
+    pub(crate) unsafe fn read<T: Copy>(
         &mut self,
         allocator: &vk_mem::Allocator,
-        data: &[T],
-    ) -> Result<(), vk::Result> {
-        let data_ptr = allocator.map_memory(&mut self.allocation)? as *mut T;
-        data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+        count: usize,
+    ) -> Result<Vec<T>, vk::Result> {
+        let data_ptr = allocator.map_memory(&mut self.allocation)? as *const T;
+        allocator.invalidate_allocation(&self.allocation, 0, vk::WHOLE_SIZE as usize)?;
+        let data = std::slice::from_raw_parts(data_ptr, count).to_vec();
         allocator.unmap_memory(&mut self.allocation);
+        Ok(data)
+    }
+    pub(crate) unsafe fn destroy(&mut self, allocator: &vk_mem::Allocator) {
+        allocator.destroy_buffer(self.buffer, &mut self.allocation);
+    }
+}
+
+//a buffer of `capacity` elements of T, written through its persistent mapping; only host-visible
+//memory can be written
+pub(crate) struct TypedBuffer<T> {
+    buffer: Buffer,
+    capacity: usize,
+    elements: std::marker::PhantomData<T>,
+}
+
+impl<T: Pod> TypedBuffer<T> {
+    pub(crate) fn new(
+        allocator: &vk_mem::Allocator,
+        capacity: usize,
+        usage: vk::BufferUsageFlags,
+        memory_usage: vk_mem::MemoryUsage,
+    ) -> Result<TypedBuffer<T>, vk::Result> {
+        let buffer = Buffer::new(
+            allocator,
+            (capacity * std::mem::size_of::<T>()) as u64,
+            usage,
+            memory_usage,
+        )?;
+        Ok(TypedBuffer {
+            buffer,
+            capacity,
+            elements: std::marker::PhantomData,
+        })
+    }
+    //a new, empty buffer of the same kind; the caller decides when the old one can be destroyed
+    pub(crate) fn reallocated(
+        &self,
+        allocator: &vk_mem::Allocator,
+        capacity: usize,
+    ) -> Result<TypedBuffer<T>, vk::Result> {
+        TypedBuffer::new(
+            allocator,
+            capacity,
+            self.buffer.usage,
+            self.buffer.memory_usage,
+        )
+    }
+    //copies `data` to the elements from `offset` on; the GPU must not be using them
+    pub(crate) fn write(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        offset: usize,
+        data: &[T],
+    ) -> Result<(), BufferError> {
+        let range = byte_range::<T>(offset, data.len(), self.capacity)?;
+        if self.buffer.mapped.is_null() {
+            return Err(BufferError::NotHostVisible);
+        }
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        unsafe {
+            self.buffer
+                .mapped
+                .add(range.start)
+                .copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        }
+        if !self.buffer.coherent {
+            allocator.flush_allocation(&self.buffer.allocation, range.start, range.len())?;
+        }
         Ok(())
     }
 }
+
+impl<T> TypedBuffer<T> {
+    pub(crate) fn buffer(&self) -> vk::Buffer {
+        self.buffer.buffer
+    }
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub(crate) unsafe fn destroy(&mut self, allocator: &vk_mem::Allocator) {
+        self.buffer.destroy(allocator);
+    }
+}
+
+//for retiring, which does not care about the element type
+impl<T> From<TypedBuffer<T>> for Buffer {
+    fn from(typed: TypedBuffer<T>) -> Buffer {
+        typed.buffer
+    }
+}
+
+//the bytes `amount` elements from `offset` on take up in a buffer of `capacity` elements
+fn byte_range<T>(
+    offset: usize,
+    amount: usize,
+    capacity: usize,
+) -> Result<std::ops::Range<usize>, BufferError> {
+    let size = std::mem::size_of::<T>();
+    match offset.checked_add(amount) {
+        Some(end) if end <= capacity => Ok(offset * size..end * size),
+        end => Err(BufferError::OutOfBounds {
+            requested: end.map_or(u64::MAX, |end| (end * size) as u64),
+            capacity: (capacity * size) as u64,
+        }),
+    }
+}
+
+//capacity to reallocate to so that `required` bytes or elements fit, doubling to keep regrowth rare
+pub(crate) fn grown_capacity(current: u64, required: u64) -> u64 {
+    let mut capacity = current.max(1);
+    while capacity < required {
+        capacity *= 2;
+    }
+    capacity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_doubles_until_the_data_fits() {
+        assert_eq!(grown_capacity(64, 65), 128);
+        assert_eq!(grown_capacity(64, 300), 512);
+        assert_eq!(grown_capacity(0, 76), 128);
+    }
+
+    #[test]
+    fn capacity_is_kept_when_the_data_already_fits() {
+        assert_eq!(grown_capacity(128, 100), 128);
+        assert_eq!(grown_capacity(128, 128), 128);
+    }
+
+    #[test]
+    fn writes_must_stay_within_the_capacity() {
+        assert_eq!(byte_range::<[f32; 4]>(2, 3, 5).unwrap(), 32..80);
+        assert_eq!(byte_range::<u16>(0, 0, 0).unwrap(), 0..0);
+        assert!(matches!(
+            byte_range::<[f32; 4]>(3, 3, 5),
+            Err(BufferError::OutOfBounds {
+                requested: 96,
+                capacity: 80
+            })
+        ));
+        assert!(matches!(
+            byte_range::<u32>(usize::MAX, 1, 5),
+            Err(BufferError::OutOfBounds {
+                requested: u64::MAX,
+                ..
+            })
+        ));
+    }
+}
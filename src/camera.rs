@@ -1,6 +1,10 @@
-use crate::buffer::Buffer;
+use crate::buffer::{BufferError, TypedBuffer};
+use crate::culling::Frustum;
 use nalgebra as na;
 
+//view matrix, projection matrix and position, the latter for specular highlights
+pub(crate) type CameraUniform = [[f32; 4]; 9];
+
 pub struct Camera {
     viewmatrix: na::Matrix4<f32>,
     position: na::Vector3<f32>,
@@ -31,9 +35,51 @@ impl Default for Camera {
     }
 }
 impl Camera {
-    pub(crate) fn update_buffer(&self, allocator: &vk_mem::Allocator, buffer: &mut Buffer) {
-        let data: [[[f32; 4]; 4]; 2] = [self.viewmatrix.into(), self.projectionmatrix.into()];
-        unsafe { buffer.fill(allocator, &data) }.unwrap();
+    //the directions do not need to be normalised, and `down_direction` is made perpendicular to
+    //`view_direction`; `fovy` is in radians
+    pub(crate) fn new(
+        position: [f32; 3],
+        view_direction: [f32; 3],
+        down_direction: [f32; 3],
+        fovy: f32,
+        near: f32,
+        far: f32,
+    ) -> Camera {
+        let view_direction = na::Vector3::from(view_direction).normalize();
+        let down_direction = na::Vector3::from(down_direction);
+        let down_direction =
+            (down_direction - view_direction * view_direction.dot(&down_direction)).normalize();
+        let mut cam = Camera {
+            position: position.into(),
+            view_direction,
+            down_direction,
+            fovy,
+            near,
+            far,
+            ..Default::default()
+        };
+        cam.update_projectionmatrix();
+        cam.update_viewmatrix();
+        cam
+    }
+    pub(crate) const BUFFER_SIZE: u64 = std::mem::size_of::<CameraUniform>() as u64;
+
+    pub(crate) fn update_buffer(
+        &self,
+        allocator: &vk_mem::Allocator,
+        buffer: &mut TypedBuffer<CameraUniform>,
+    ) -> Result<(), BufferError> {
+        let viewmatrix: [[f32; 4]; 4] = self.viewmatrix.into();
+        let projectionmatrix: [[f32; 4]; 4] = self.projectionmatrix.into();
+        let mut data: CameraUniform = [[0.0; 4]; 9];
+        data[0..4].copy_from_slice(&viewmatrix);
+        data[4..8].copy_from_slice(&projectionmatrix);
+        data[8] = [self.position.x, self.position.y, self.position.z, 1.0];
+        buffer.write(allocator, 0, &[data])
+    }
+    //what the camera sees, for culling
+    pub(crate) fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.projectionmatrix * self.viewmatrix))
     }
     fn update_viewmatrix(&mut self) {
         let right = na::Unit::new_normalize(self.down_direction.cross(&self.view_direction));
@@ -78,6 +124,28 @@ impl Camera {
             0.0,
         );
     }
+    pub(crate) fn position(&self) -> [f32; 3] {
+        self.position.into()
+    }
+    pub(crate) fn view_direction(&self) -> [f32; 3] {
+        self.view_direction.into()
+    }
+    pub(crate) fn down_direction(&self) -> [f32; 3] {
+        self.down_direction.into()
+    }
+    pub(crate) fn fovy(&self) -> f32 {
+        self.fovy
+    }
+    pub(crate) fn near(&self) -> f32 {
+        self.near
+    }
+    pub(crate) fn far(&self) -> f32 {
+        self.far
+    }
+    pub(crate) fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+        self.update_projectionmatrix();
+    }
     pub(crate) fn move_forward(&mut self, distance: f32) {
         self.position += distance * self.view_direction;
         self.update_viewmatrix();
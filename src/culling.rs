@@ -0,0 +1,157 @@
+//skipping instances the camera cannot see, by testing the bounds of their model's vertices against
+//the view frustum before the instances are uploaded
+use nalgebra as na;
+
+//vertex types with a position in model space
+pub(crate) trait VertexPosition {
+    fn position(&self) -> [f32; 3];
+}
+
+//instance types that place their model in the world
+pub(crate) trait InstanceTransform {
+    fn modelmatrix(&self) -> na::Matrix4<f32>;
+}
+
+//in model space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Bounds {
+    pub(crate) min: [f32; 3],
+    pub(crate) max: [f32; 3],
+    //around the centre of the box, so not always the smallest sphere, but never much bigger
+    pub(crate) centre: [f32; 3],
+    pub(crate) radius: f32,
+}
+
+impl Bounds {
+    //None without any positions
+    pub(crate) fn of(positions: impl IntoIterator<Item = [f32; 3]>) -> Option<Bounds> {
+        let positions: Vec<na::Point3<f32>> = positions.into_iter().map(Into::into).collect();
+        let first = *positions.first()?;
+        let (min, max) = positions
+            .iter()
+            .fold((first, first), |(min, max), p| (min.inf(p), max.sup(p)));
+        let centre = na::center(&min, &max);
+        let radius = positions
+            .iter()
+            .map(|p| na::distance(&centre, p))
+            .fold(0.0, f32::max);
+        Some(Bounds {
+            min: min.into(),
+            max: max.into(),
+            centre: centre.into(),
+            radius,
+        })
+    }
+    fn corners(&self) -> [na::Point3<f32>; 8] {
+        let [min, max] = [self.min, self.max];
+        std::array::from_fn(|i| {
+            na::Point3::new(
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            )
+        })
+    }
+}
+
+//planes with normals pointing inwards, as (normal, distance) so that normal·p + distance >= 0 for
+//every point p inside
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Frustum {
+    planes: [na::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    //for a projection * view matrix with Vulkan's clip space, where 0 <= z <= w
+    pub(crate) fn from_matrix(m: &na::Matrix4<f32>) -> Frustum {
+        let row = |i| m.row(i).transpose();
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        Frustum {
+            planes: planes.map(|plane| plane / plane.xyz().norm()),
+        }
+    }
+    //left, right, top, bottom, near and far
+    pub(crate) fn planes(&self) -> &[na::Vector4<f32>; 6] {
+        &self.planes
+    }
+    fn distance(plane: &na::Vector4<f32>, point: &na::Point3<f32>) -> f32 {
+        plane.xyz().dot(&point.coords) + plane.w
+    }
+    //conservative, so some instances just outside a corner of the frustum are still drawn
+    pub(crate) fn may_see(&self, bounds: &Bounds, modelmatrix: &na::Matrix4<f32>) -> bool {
+        //the sphere decides most cases, growing with the biggest scale of the model matrix
+        let centre = modelmatrix.transform_point(&bounds.centre.into());
+        let scale = (0..3)
+            .map(|column| modelmatrix.fixed_view::<3, 1>(0, column).norm())
+            .fold(0.0, f32::max);
+        let radius = bounds.radius * scale;
+        let mut fully_inside = true;
+        for plane in &self.planes {
+            let distance = Self::distance(plane, &centre);
+            if distance < -radius {
+                return false;
+            }
+            fully_inside &= distance >= radius;
+        }
+        if fully_inside {
+            return true;
+        }
+        //near the edges, the box can be outside where its sphere is not
+        let corners = bounds
+            .corners()
+            .map(|corner| modelmatrix.transform_point(&corner));
+        self.planes.iter().all(|plane| {
+            corners
+                .iter()
+                .any(|corner| Self::distance(plane, corner) >= 0.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn bounds_enclose_every_position() {
+        let bounds = Bounds::of([[0.0, 0.0, 0.0], [2.0, 1.0, 0.0], [1.0, -1.0, 4.0]]).unwrap();
+        assert_eq!(bounds.min, [0.0, -1.0, 0.0]);
+        assert_eq!(bounds.max, [2.0, 1.0, 4.0]);
+        assert_eq!(bounds.centre, [1.0, 0.0, 2.0]);
+        assert!((bounds.radius - 6f32.sqrt()).abs() < 1e-6);
+        assert_eq!(Bounds::of([]), None);
+    }
+
+    #[test]
+    fn only_instances_outside_the_frustum_are_culled() {
+        //at the origin looking along +z, with +y down
+        let camera = Camera::new(
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0],
+            std::f32::consts::FRAC_PI_2,
+            0.1,
+            100.0,
+        );
+        let frustum = camera.frustum();
+        let bounds = Bounds::of([[-0.5, -0.5, -0.5], [0.5, 0.5, 0.5]]).unwrap();
+        let at = |x, y, z| na::Matrix4::new_translation(&na::Vector3::new(x, y, z));
+        assert!(frustum.may_see(&bounds, &at(0.0, 0.0, 5.0)));
+        assert!(!frustum.may_see(&bounds, &at(0.0, 0.0, -5.0)));
+        assert!(!frustum.may_see(&bounds, &at(0.0, 0.0, 200.0)));
+        assert!(!frustum.may_see(&bounds, &at(50.0, 0.0, 5.0)));
+        //partly inside the left edge
+        assert!(frustum.may_see(&bounds, &at(-6.9, 0.0, 5.0)));
+        //scaling up reaches back into view
+        let scaled = at(0.0, 0.0, -5.0) * na::Matrix4::new_scaling(20.0);
+        assert!(frustum.may_see(&bounds, &scaled));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::buffer::{Buffer, TypedBuffer};
+use crate::camera::{Camera, CameraUniform};
+use crate::commandbuffers::{create_commandbuffers, Pools};
+use crate::lights::{Lighting, LightingUniform};
+use ash::vk;
+
+//everything the CPU writes or waits on while recording one frame; the GPU may still be reading
+//the other frames in flight, so none of this is shared between them
+pub(crate) struct Frame {
+    pub(crate) image_available: vk::Semaphore,
+    pub(crate) rendering_finished: vk::Semaphore,
+    pub(crate) may_begin_drawing: vk::Fence,
+    pub(crate) commandbuffer: vk::CommandBuffer,
+    pub(crate) uniformbuffer: TypedBuffer<CameraUniform>,
+    pub(crate) lightbuffer: TypedBuffer<LightingUniform>,
+    pub(crate) descriptor_set: vk::DescriptorSet,
+    //buffers replaced while recording this frame, freed the next time its fence has been waited on
+    pub(crate) retired_buffers: Vec<Buffer>,
+    //signalled by the uploads this frame waits for, handed back to the uploader with the buffers
+    pub(crate) upload_finished: Option<vk::Semaphore>,
+    //for the culling compute shader, emptied whenever the frame is recorded again; a bigger pool is
+    //added when the last one runs out
+    culling_descriptor_pools: Vec<vk::DescriptorPool>,
+    //of the last pool
+    culling_descriptor_sets_per_pool: u32,
+}
+
+impl Frame {
+    pub(crate) fn init(
+        logical_device: &ash::Device,
+        pools: &Pools,
+        allocator: &vk_mem::Allocator,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<Frame, vk::Result> {
+        let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
+        let image_available = unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
+        let rendering_finished = unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
+        //signalled, so the first wait on a fresh frame returns straight away
+        let fenceinfo = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+        let may_begin_drawing = unsafe { logical_device.create_fence(&fenceinfo, None) }?;
+        let commandbuffer = create_commandbuffers(logical_device, pools, 1)?[0];
+
+        let uniformbuffer = TypedBuffer::new(
+            allocator,
+            1,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk_mem::MemoryUsage::CpuToGpu,
+        )?;
+        let lightbuffer = TypedBuffer::new(
+            allocator,
+            1,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk_mem::MemoryUsage::CpuToGpu,
+        )?;
+        let desc_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&desc_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+        let camera_buffer_infos = [vk::DescriptorBufferInfo {
+            buffer: uniformbuffer.buffer(),
+            offset: 0,
+            range: Camera::BUFFER_SIZE,
+        }];
+        let light_buffer_infos = [vk::DescriptorBufferInfo {
+            buffer: lightbuffer.buffer(),
+            offset: 0,
+            range: Lighting::BUFFER_SIZE,
+        }];
+        let desc_sets_write = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&camera_buffer_infos)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&light_buffer_infos)
+                .build(),
+        ];
+        unsafe { logical_device.update_descriptor_sets(&desc_sets_write, &[]) };
+
+        Ok(Frame {
+            image_available,
+            rendering_finished,
+            may_begin_drawing,
+            commandbuffer,
+            uniformbuffer,
+            lightbuffer,
+            descriptor_set,
+            retired_buffers: vec![],
+            upload_finished: None,
+            culling_descriptor_pools: vec![],
+            culling_descriptor_sets_per_pool: 8,
+        })
+    }
+    //only call once may_begin_drawing has been waited on
+    pub(crate) unsafe fn free_retired_buffers(&mut self, allocator: &vk_mem::Allocator) {
+        for mut buffer in self.retired_buffers.drain(..) {
+            buffer.destroy(allocator);
+        }
+    }
+    //only call once may_begin_drawing has been waited on, the sets from before become invalid
+    pub(crate) unsafe fn reset_culling_descriptor_sets(
+        &mut self,
+        logical_device: &ash::Device,
+    ) -> Result<(), vk::Result> {
+        let Some(last) = self.culling_descriptor_pools.pop() else {
+            return Ok(());
+        };
+        for pool in self.culling_descriptor_pools.drain(..) {
+            logical_device.destroy_descriptor_pool(pool, None);
+        }
+        self.culling_descriptor_pools.push(last);
+        logical_device.reset_descriptor_pool(last, vk::DescriptorPoolResetFlags::empty())
+    }
+    //for the five storage buffers of cull.comp, valid until the next reset
+    pub(crate) fn allocate_culling_descriptor_set(
+        &mut self,
+        logical_device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        let layouts = [layout];
+        if let Some(&pool) = self.culling_descriptor_pools.last() {
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+            match unsafe { logical_device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(sets) => return Ok(sets[0]),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let amount_of_sets = 2 * self.culling_descriptor_sets_per_pool;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 5 * amount_of_sets,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(amount_of_sets)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        self.culling_descriptor_pools.push(pool);
+        self.culling_descriptor_sets_per_pool = amount_of_sets;
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        Ok(unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }?[0])
+    }
+    //the command buffer and descriptor set go away with their pools
+    pub(crate) unsafe fn cleanup(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &vk_mem::Allocator,
+    ) {
+        self.free_retired_buffers(allocator);
+        self.uniformbuffer.destroy(allocator);
+        self.lightbuffer.destroy(allocator);
+        for pool in self.culling_descriptor_pools.drain(..) {
+            logical_device.destroy_descriptor_pool(pool, None);
+        }
+        if let Some(semaphore) = self.upload_finished.take() {
+            logical_device.destroy_semaphore(semaphore, None);
+        }
+        logical_device.destroy_fence(self.may_begin_drawing, None);
+        logical_device.destroy_semaphore(self.rendering_finished, None);
+        logical_device.destroy_semaphore(self.image_available, None);
+    }
+}
+
+pub(crate) fn init_descriptor_pool(
+    logical_device: &ash::Device,
+    amount_of_frames: u32,
+) -> Result<vk::DescriptorPool, vk::Result> {
+    //camera and lights
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 2 * amount_of_frames,
+    }];
+    let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .max_sets(amount_of_frames)
+        .pool_sizes(&pool_sizes);
+    unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }
+}
@@ -0,0 +1,300 @@
+//glTF 2.0 import (.gltf with external or embedded buffers, and .glb): every triangle primitive
+//becomes one mesh, and every node referencing it one instance of that mesh
+use crate::model::InstanceData;
+use nalgebra as na;
+use std::path::Path;
+
+#[derive(Debug)]
+pub(crate) enum GltfError {
+    Gltf(gltf::Error),
+    MissingPositions {
+        mesh: usize,
+        primitive: usize,
+    },
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        index: u32,
+        vertices: usize,
+    },
+}
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GltfError::Gltf(e) => write!(f, "could not load glTF: {}", e),
+            GltfError::MissingPositions { mesh, primitive } => {
+                write!(
+                    f,
+                    "primitive {} of mesh {} has no positions",
+                    primitive, mesh
+                )
+            }
+            GltfError::IndexOutOfRange {
+                mesh,
+                primitive,
+                index,
+                vertices,
+            } => write!(
+                f,
+                "primitive {} of mesh {} uses vertex {}, but has only {}",
+                primitive, mesh, index, vertices
+            ),
+        }
+    }
+}
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Gltf(e) => Some(e),
+            GltfError::MissingPositions { .. } | GltfError::IndexOutOfRange { .. } => None,
+        }
+    }
+}
+impl From<gltf::Error> for GltfError {
+    fn from(e: gltf::Error) -> Self {
+        GltfError::Gltf(e)
+    }
+}
+
+//normals and texcoords are empty if the file has none
+#[derive(Debug, Default, Clone)]
+pub(crate) struct GltfPrimitive {
+    pub(crate) positions: Vec<[f32; 3]>,
+    pub(crate) normals: Vec<[f32; 3]>,
+    pub(crate) texcoords: Vec<[f32; 2]>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) instances: Vec<InstanceData>,
+}
+
+//primitives no node of the scene uses are left out
+pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<Vec<GltfPrimitive>, GltfError> {
+    let path = path.as_ref();
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+
+    //indexed by mesh, then by primitive; None for the ones that are not triangles
+    let mut meshes = vec![];
+    for mesh in document.meshes() {
+        let mut primitives = vec![];
+        for primitive in mesh.primitives() {
+            primitives.push(read_primitive(&mesh, &primitive, &buffers)?);
+        }
+        meshes.push(primitives);
+    }
+
+    //the default scene, or every scene if the file does not name one
+    let scenes: Vec<gltf::Scene> = match document.default_scene() {
+        Some(scene) => vec![scene],
+        None => document.scenes().collect(),
+    };
+    for scene in scenes {
+        for node in scene.nodes() {
+            instantiate(&node, na::Matrix4::identity(), &mut meshes);
+        }
+    }
+    Ok(meshes
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|primitive| !primitive.instances.is_empty())
+        .collect())
+}
+
+fn read_primitive(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Option<GltfPrimitive>, GltfError> {
+    use gltf::mesh::Mode;
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+    let positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .ok_or(GltfError::MissingPositions {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+        })?
+        .collect();
+    let normals = reader
+        .read_normals()
+        .map(|normals| normals.collect())
+        .unwrap_or_default();
+    let texcoords = reader
+        .read_tex_coords(0)
+        .map(|texcoords| texcoords.into_f32().collect())
+        .unwrap_or_default();
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    //a broken file must not make the mesh processing index past the vertices
+    if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        return Err(GltfError::IndexOutOfRange {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+            index,
+            vertices: positions.len(),
+        });
+    }
+    let indices = match primitive.mode() {
+        Mode::Triangles => indices,
+        //every other triangle of a strip is wound the other way round
+        Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+            .flat_map(|i| {
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i + 1], indices[i], indices[i + 2]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+            .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+            .collect(),
+        //the pipeline only rasterises triangles
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => return Ok(None),
+    };
+    Ok(Some(GltfPrimitive {
+        positions,
+        normals,
+        texcoords,
+        indices,
+        instances: vec![],
+    }))
+}
+
+fn instantiate(
+    node: &gltf::Node,
+    parent_transform: na::Matrix4<f32>,
+    meshes: &mut [Vec<Option<GltfPrimitive>>],
+) {
+    //glTF matrices are column-major, as are nalgebra's
+    let local = na::Matrix4::from(node.transform().matrix());
+    let transform = parent_transform * local;
+    if let Some(mesh) = node.mesh() {
+        for (primitive, imported) in mesh.primitives().zip(meshes[mesh.index()].iter_mut()) {
+            if let Some(imported) = imported {
+                let [r, g, b, _] = primitive
+                    .material()
+                    .pbr_metallic_roughness()
+                    .base_color_factor();
+                imported.instances.push(InstanceData {
+                    modelmatrix: transform.into(),
+                    colour: [r, g, b],
+                });
+            }
+        }
+    }
+    for child in node.children() {
+        instantiate(&child, transform, meshes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //one triangle with normals and u16 indices, followed by two bytes of padding
+    fn triangle_buffer(indices: [u16; 3]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        for _ in 0..3 {
+            for value in [0.0f32, 0.0, 1.0] {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        for index in indices {
+            bytes.extend(index.to_le_bytes());
+        }
+        bytes.extend([0, 0]);
+        bytes
+    }
+
+    //writes SCENE and its buffer to a directory of its own and loads it from there
+    fn load_scene(name: &str, buffer: Vec<u8>) -> Result<Vec<GltfPrimitive>, GltfError> {
+        let directory =
+            std::env::temp_dir().join(format!("gltfloader-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("triangle.bin"), buffer).unwrap();
+        std::fs::write(directory.join("scene.gltf"), SCENE).unwrap();
+        let primitives = load(directory.join("scene.gltf"));
+        std::fs::remove_dir_all(&directory).unwrap();
+        primitives
+    }
+
+    const SCENE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 2] }],
+        "nodes": [
+            { "translation": [1, 0, 0], "children": [1] },
+            { "mesh": 0, "scale": [2, 2, 2] },
+            { "mesh": 0 }
+        ],
+        "meshes": [{ "primitives": [{
+            "attributes": { "POSITION": 0, "NORMAL": 1 },
+            "indices": 2,
+            "material": 0
+        }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.25, 1, 1] } }],
+        "buffers": [{ "uri": "triangle.bin", "byteLength": 80 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 72, "byteLength": 6 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+              "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+            { "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ]
+    }"#;
+
+    #[test]
+    fn nodes_become_instances_with_world_transforms() {
+        let primitives = load_scene("instances", triangle_buffer([0, 1, 2])).unwrap();
+        assert_eq!(primitives.len(), 1);
+        let primitive = &primitives[0];
+        assert_eq!(primitive.positions[1], [1.0, 0.0, 0.0]);
+        assert_eq!(primitive.normals, vec![[0.0, 0.0, 1.0]; 3]);
+        assert_eq!(primitive.indices, vec![0, 1, 2]);
+        assert_eq!(primitive.instances.len(), 2);
+        let child = na::Matrix4::from(primitive.instances[0].modelmatrix);
+        let expected = na::Matrix4::new_translation(&na::Vector3::new(1.0, 0.0, 0.0))
+            * na::Matrix4::new_scaling(2.0);
+        assert_eq!(child, expected);
+        assert_eq!(
+            na::Matrix4::from(primitive.instances[1].modelmatrix),
+            na::Matrix4::identity()
+        );
+        assert_eq!(primitive.instances[0].colour, [0.5, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn indices_past_the_vertices_are_reported() {
+        let error = load_scene("out-of-range", triangle_buffer([0, 1, 3])).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                GltfError::IndexOutOfRange {
+                    mesh: 0,
+                    primitive: 0,
+                    index: 3,
+                    vertices: 3
+                }
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        assert!(matches!(
+            load("does/not/exist.gltf"),
+            Err(GltfError::Gltf(_))
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+//golden-image regression tests: fixed scenes are rendered headless and compared against the
+//reference PNGs in tests/golden, whose README explains how to record them. Set UPDATE_GOLDEN=1 to
+//(re)write the references after an intended visual change, and GRAPHICS_REQUIRE_VULKAN=1 on CI so a
+//missing driver fails the run instead of skipping it. Failing comparisons leave the render and a
+//diff in target/golden.
+
+use crate::camera::Camera;
+use crate::lights::Lighting;
+use crate::model::{InstanceData, Model, VertexData};
+use crate::offscreen::write_png;
+use crate::vkinterface::{RendererConfig, VkInterface};
+use ash::vk;
+use nalgebra as na;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+//largest per-channel difference that still counts as the same pixel, to absorb driver rounding
+const TOLERANCE: u8 = 2;
+
+struct Comparison {
+    mismatched_pixels: usize,
+    diff: Vec<u8>,
+}
+
+//marks differing pixels red on top of a faded copy of the reference
+fn compare(reference: &[u8], actual: &[u8], tolerance: u8) -> Comparison {
+    assert_eq!(reference.len(), actual.len(), "images differ in size");
+    let mut mismatched_pixels = 0;
+    let mut diff = Vec::with_capacity(reference.len());
+    for (expected, got) in reference.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let differs = expected
+            .iter()
+            .zip(got)
+            .any(|(e, g)| e.abs_diff(*g) > tolerance);
+        if differs {
+            mismatched_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let grey = (expected[0] as u16 + expected[1] as u16 + expected[2] as u16) / 12;
+            diff.extend_from_slice(&[grey as u8, grey as u8, grey as u8, 255]);
+        }
+    }
+    Comparison {
+        mismatched_pixels,
+        diff,
+    }
+}
+
+fn read_png(path: &std::path::Path) -> Result<(vk::Extent2D, Vec<u8>), Box<dyn std::error::Error>> {
+    let decoder = png::Decoder::new(std::fs::File::open(path)?);
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(format!("{} is not an RGBA8 image", path.display()).into());
+    }
+    pixels.truncate(info.buffer_size());
+    Ok((
+        vk::Extent2D {
+            width: info.width,
+            height: info.height,
+        },
+        pixels,
+    ))
+}
+
+//None if this machine has no usable Vulkan implementation
+fn render(models: Vec<Model<VertexData, InstanceData>>) -> Option<Vec<u8>> {
+    let config = RendererConfig {
+        shader_directory: std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders"),
+        //every render starts from scratch and leaves nothing behind
+        pipeline_cache: None,
+        ..Default::default()
+    };
+    let mut vk_struct = match VkInterface::init_headless(WIDTH, HEIGHT, config) {
+        Ok(vk_struct) => vk_struct,
+        Err(e) if std::env::var_os("GRAPHICS_REQUIRE_VULKAN").is_none() => {
+            eprintln!("skipping golden-image test, no Vulkan available: {}", e);
+            return None;
+        }
+        Err(e) => panic!("could not create a headless renderer: {}", e),
+    };
+    vk_struct.models = models;
+    for m in &mut vk_struct.models {
+        m.update_vertexbuffer(&vk_struct.allocator, &mut vk_struct.uploader)
+            .unwrap();
+        m.update_instancebuffer(&vk_struct.allocator, vk_struct.current_frame)
+            .unwrap();
+    }
+    let mut camera = Camera::default();
+    camera.set_aspect(WIDTH as f32 / HEIGHT as f32);
+    camera
+        .update_buffer(&vk_struct.allocator, &mut vk_struct.frames[0].uniformbuffer)
+        .unwrap();
+    Lighting::default()
+        .update_buffer(&vk_struct.allocator, &mut vk_struct.frames[0].lightbuffer)
+        .unwrap();
+    Some(vk_struct.render_offscreen().expect("offscreen rendering"))
+}
+
+fn check_golden(name: &str, models: Vec<Model<VertexData, InstanceData>>) {
+    let Some(actual) = render(models) else {
+        return;
+    };
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = root.join("tests/golden").join(format!("{}.png", name));
+    let extent = vk::Extent2D {
+        width: WIDTH,
+        height: HEIGHT,
+    };
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        write_png(&reference_path, extent, &actual).unwrap();
+        return;
+    }
+    //recording needs a Vulkan driver, so a fresh checkout can lack references the tests expect
+    assert!(
+        reference_path.exists(),
+        "{} has not been recorded yet; render it with `UPDATE_GOLDEN=1 cargo test {}` on a machine \
+         with Vulkan, check that it looks right and commit it (see tests/golden/README.md)",
+        reference_path.display(),
+        name
+    );
+    let (reference_extent, reference) = read_png(&reference_path).unwrap_or_else(|e| {
+        panic!(
+            "could not read the reference {} ({}), rerun with UPDATE_GOLDEN=1 to record it again",
+            reference_path.display(),
+            e
+        )
+    });
+    assert_eq!(
+        (reference_extent.width, reference_extent.height),
+        (WIDTH, HEIGHT),
+        "reference {} has the wrong size",
+        reference_path.display()
+    );
+    let comparison = compare(&reference, &actual, TOLERANCE);
+    if comparison.mismatched_pixels > 0 {
+        let output_dir = root.join("target/golden");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let actual_path = output_dir.join(format!("{}.actual.png", name));
+        let diff_path = output_dir.join(format!("{}.diff.png", name));
+        write_png(&actual_path, extent, &actual).unwrap();
+        write_png(&diff_path, extent, &comparison.diff).unwrap();
+        panic!(
+            "{} pixels of {} differ from {}, see {} and {}",
+            comparison.mismatched_pixels,
+            name,
+            reference_path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn example_scene_matches_reference() {
+    check_golden("example_scene", crate::example_scene());
+}
+
+#[test]
+fn single_cube_matches_reference() {
+    let mut cube = Model::cube();
+    cube.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::from_scaled_axis(na::Vector3::new(0.3, 0.5, 0.0))
+            * na::Matrix4::new_scaling(0.8))
+        .into(),
+        colour: [1.0, 0.5, 0.0],
+    });
+    check_golden("single_cube", vec![cube]);
+}
+
+#[test]
+fn identical_images_have_no_mismatches() {
+    let image = [10, 20, 30, 255, 200, 100, 0, 255];
+    assert_eq!(compare(&image, &image, 0).mismatched_pixels, 0);
+}
+
+#[test]
+fn differences_within_tolerance_are_accepted() {
+    let reference = [10, 20, 30, 255, 200, 100, 0, 255];
+    let actual = [12, 18, 30, 255, 203, 100, 0, 255];
+    assert_eq!(compare(&reference, &actual, 2).mismatched_pixels, 1);
+    assert_eq!(compare(&reference, &actual, 3).mismatched_pixels, 0);
+}
+
+#[test]
+fn diff_image_marks_mismatches_red() {
+    let reference = [0, 0, 0, 255, 120, 120, 120, 255];
+    let actual = [0, 0, 0, 255, 0, 0, 0, 255];
+    let comparison = compare(&reference, &actual, 0);
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(&comparison.diff[0..4], &[0, 0, 0, 255]);
+    assert_eq!(&comparison.diff[4..8], &[255, 0, 0, 255]);
+}
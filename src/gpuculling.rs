@@ -0,0 +1,388 @@
+//culling in a compute shader, for scenes with so many instances that testing and uploading them on
+//the CPU every frame takes too long; the visible instances stay in device-local memory, and each
+//frame shaders/culling/cull.comp copies those the camera may see into the buffer that is drawn
+//from, together with the instance count of an indirect draw
+use crate::buffer::{Buffer, BufferError, TypedBuffer};
+use crate::culling::{Bounds, Frustum};
+use crate::model::InstanceData;
+use crate::rendering::ComputePipeline;
+use ash::vk;
+use std::ops::Range;
+
+//local_size_x of cull.comp
+const GROUP_SIZE: usize = 256;
+
+//how many workgroups cull.comp is dispatched with for `amount` instances
+fn workgroups(amount: usize) -> u32 {
+    amount.div_ceil(GROUP_SIZE) as u32
+}
+
+//the push constants of cull.comp
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct CullingConstants {
+    planes: [[f32; 4]; 6],
+    //centre and radius
+    sphere: [f32; 4],
+    amount: u32,
+    //0 to test the instances, 1 to move those the camera may see
+    pass: u32,
+    padding: [u32; 2],
+}
+unsafe impl bytemuck::Zeroable for CullingConstants {}
+unsafe impl bytemuck::Pod for CullingConstants {}
+
+//the draw a model makes, before the shader has filled in how many instances it draws
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum DrawCommand {
+    Indexed { index_count: u32 },
+    Plain { vertex_count: u32 },
+}
+impl DrawCommand {
+    //a VkDrawIndexedIndirectCommand or a VkDrawIndirectCommand and one unused word
+    fn words(&self) -> [u32; 5] {
+        match *self {
+            DrawCommand::Indexed { index_count } => [index_count, 0, 0, 0, 0],
+            DrawCommand::Plain { vertex_count } => [vertex_count, 0, 0, 0, 0],
+        }
+    }
+}
+
+//the buffers of one model
+pub(crate) struct GpuInstances {
+    //every visible instance, slot for slot, with room for `capacity`; only changed ranges are
+    //copied in
+    instances: Buffer,
+    //those the camera may see, packed to the front by the shader and read as the instance buffer
+    drawn: Buffer,
+    command: Buffer,
+    //the shader's bookkeeping between its two passes
+    slots: Buffer,
+    group_counts: Buffer,
+    capacity: usize,
+    //the staging buffer and regions of the instances changed since the last frame, copied over
+    //before culling
+    staged: Option<(vk::Buffer, Vec<vk::BufferCopy>)>,
+    draw: DrawCommand,
+    constants: CullingConstants,
+}
+
+impl GpuInstances {
+    //empty, with room for `capacity` instances
+    pub(crate) fn new(
+        allocator: &vk_mem::Allocator,
+        capacity: usize,
+        draw: DrawCommand,
+    ) -> Result<GpuInstances, BufferError> {
+        let storage = vk::BufferUsageFlags::STORAGE_BUFFER;
+        let instance_bytes = (capacity * std::mem::size_of::<InstanceData>()) as u64;
+        let sizes_and_usages = [
+            (instance_bytes, storage | vk::BufferUsageFlags::TRANSFER_DST),
+            (
+                instance_bytes,
+                storage | vk::BufferUsageFlags::VERTEX_BUFFER,
+            ),
+            (
+                std::mem::size_of::<[u32; 5]>() as u64,
+                storage
+                    | vk::BufferUsageFlags::INDIRECT_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+            ),
+            (capacity as u64 * 4, storage),
+            (workgroups(capacity) as u64 * 4, storage),
+        ];
+        let mut buffers = Vec::with_capacity(sizes_and_usages.len());
+        for (size, usage) in sizes_and_usages {
+            match Buffer::new(allocator, size, usage, vk_mem::MemoryUsage::GpuOnly) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(e) => {
+                    for mut buffer in buffers {
+                        unsafe { buffer.destroy(allocator) };
+                    }
+                    return Err(e.into());
+                }
+            }
+        }
+        let Ok([instances, drawn, command, slots, group_counts]) = <[Buffer; 5]>::try_from(buffers)
+        else {
+            unreachable!("one buffer per size")
+        };
+        Ok(GpuInstances {
+            instances,
+            drawn,
+            command,
+            slots,
+            group_counts,
+            capacity,
+            staged: None,
+            draw,
+            constants: CullingConstants {
+                planes: [[0.0; 4]; 6],
+                sphere: [0.0; 4],
+                amount: 0,
+                pass: 0,
+                padding: [0; 2],
+            },
+        })
+    }
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+    //stages the `dirty` ranges of `visible` to be copied in with the next frame, replacing what
+    //was staged for the last one; the returned staging buffer has to outlive that frame
+    pub(crate) fn stage(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        visible: &[InstanceData],
+        dirty: &[Range<usize>],
+    ) -> Result<Option<Buffer>, BufferError> {
+        self.staged = None;
+        let dirty: Vec<Range<usize>> = dirty
+            .iter()
+            .map(|range| range.start.min(visible.len())..range.end.min(visible.len()))
+            .filter(|range| !range.is_empty())
+            .collect();
+        let amount: usize = dirty.iter().map(ExactSizeIterator::len).sum();
+        if amount == 0 {
+            return Ok(None);
+        }
+        let mut staging = TypedBuffer::new(
+            allocator,
+            amount,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk_mem::MemoryUsage::CpuOnly,
+        )?;
+        let size = std::mem::size_of::<InstanceData>() as u64;
+        let mut regions = Vec::with_capacity(dirty.len());
+        let mut offset = 0;
+        for range in dirty {
+            if let Err(e) = staging.write(allocator, offset, &visible[range.clone()]) {
+                unsafe { staging.destroy(allocator) };
+                return Err(e);
+            }
+            regions.push(vk::BufferCopy {
+                src_offset: offset as u64 * size,
+                dst_offset: range.start as u64 * size,
+                size: range.len() as u64 * size,
+            });
+            offset += range.len();
+        }
+        self.staged = Some((staging.buffer(), regions));
+        Ok(Some(staging.into()))
+    }
+    //for the next frame; `amount` may be smaller than the capacity, but not bigger
+    pub(crate) fn prepare(
+        &mut self,
+        amount: usize,
+        bounds: &Bounds,
+        frustum: &Frustum,
+        draw: DrawCommand,
+    ) {
+        let [x, y, z] = bounds.centre;
+        self.constants.planes = frustum.planes().map(Into::into);
+        self.constants.sphere = [x, y, z, bounds.radius];
+        self.constants.amount = amount as u32;
+        self.draw = draw;
+    }
+    //for models that are about to be dropped or need fresh buffers
+    pub(crate) fn into_buffers(self) -> [Buffer; 5] {
+        [
+            self.instances,
+            self.drawn,
+            self.command,
+            self.slots,
+            self.group_counts,
+        ]
+    }
+    unsafe fn write_descriptors(&self, logical_device: &ash::Device, set: vk::DescriptorSet) {
+        let buffers = [
+            &self.instances,
+            &self.drawn,
+            &self.command,
+            &self.slots,
+            &self.group_counts,
+        ];
+        let infos = buffers.map(|buffer| {
+            [vk::DescriptorBufferInfo {
+                buffer: buffer.buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(info)
+                    .build()
+            })
+            .collect();
+        logical_device.update_descriptor_sets(&writes, &[]);
+    }
+    //binds the drawn instances to binding 1; vertex and index buffer have to be bound already
+    pub(crate) unsafe fn record_draw(
+        &self,
+        logical_device: &ash::Device,
+        commandbuffer: vk::CommandBuffer,
+    ) {
+        logical_device.cmd_bind_vertex_buffers(commandbuffer, 1, &[self.drawn.buffer], &[0]);
+        match self.draw {
+            DrawCommand::Indexed { .. } => logical_device.cmd_draw_indexed_indirect(
+                commandbuffer,
+                self.command.buffer,
+                0,
+                1,
+                0,
+            ),
+            DrawCommand::Plain { .. } => {
+                logical_device.cmd_draw_indirect(commandbuffer, self.command.buffer, 0, 1, 0)
+            }
+        }
+    }
+}
+
+//records the culling of every model in `culled` ahead of the render pass that draws them, with one
+//descriptor set each for `pipeline`'s only layout
+pub(crate) unsafe fn record(
+    logical_device: &ash::Device,
+    commandbuffer: vk::CommandBuffer,
+    pipeline: &ComputePipeline,
+    culled: &[(&GpuInstances, vk::DescriptorSet)],
+) {
+    if culled.is_empty() {
+        return;
+    }
+    //the previous frame may still be culling with or drawing from the buffers written here
+    logical_device.cmd_pipeline_barrier(
+        commandbuffer,
+        vk::PipelineStageFlags::VERTEX_INPUT
+            | vk::PipelineStageFlags::DRAW_INDIRECT
+            | vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[],
+    );
+    for (instances, _) in culled {
+        if let Some((staging, regions)) = &instances.staged {
+            logical_device.cmd_copy_buffer(
+                commandbuffer,
+                *staging,
+                instances.instances.buffer,
+                regions,
+            );
+        }
+        logical_device.cmd_update_buffer(
+            commandbuffer,
+            instances.command.buffer,
+            0,
+            bytemuck::bytes_of(&instances.draw.words()),
+        );
+    }
+    let copied = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+        .build();
+    logical_device.cmd_pipeline_barrier(
+        commandbuffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[copied],
+        &[],
+        &[],
+    );
+    logical_device.cmd_bind_pipeline(
+        commandbuffer,
+        vk::PipelineBindPoint::COMPUTE,
+        pipeline.pipeline,
+    );
+    for (instances, set) in culled {
+        instances.write_descriptors(logical_device, *set);
+    }
+    for pass in 0..2 {
+        if pass == 1 {
+            //the second pass reads the slots and counts of every workgroup
+            let tested = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .build();
+            logical_device.cmd_pipeline_barrier(
+                commandbuffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[tested],
+                &[],
+                &[],
+            );
+        }
+        for (instances, set) in culled {
+            logical_device.cmd_bind_descriptor_sets(
+                commandbuffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.layout,
+                0,
+                &[*set],
+                &[],
+            );
+            let constants = CullingConstants {
+                pass,
+                ..instances.constants
+            };
+            logical_device.cmd_push_constants(
+                commandbuffer,
+                pipeline.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+            logical_device.cmd_dispatch(commandbuffer, workgroups(constants.amount as usize), 1, 1);
+        }
+    }
+    let culling_finished = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDIRECT_COMMAND_READ,
+        )
+        .build();
+    logical_device.cmd_pipeline_barrier(
+        commandbuffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::DRAW_INDIRECT,
+        vk::DependencyFlags::empty(),
+        &[culling_finished],
+        &[],
+        &[],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_match_the_shader() {
+        //FLOATS_PER_INSTANCE and the push constant block of cull.comp
+        assert_eq!(std::mem::size_of::<InstanceData>(), 19 * 4);
+        assert_eq!(std::mem::size_of::<CullingConstants>(), 128);
+        assert_eq!(
+            DrawCommand::Plain { vertex_count: 36 }.words(),
+            [36, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn every_instance_gets_an_invocation() {
+        assert_eq!(workgroups(0), 0);
+        assert_eq!(workgroups(1), 1);
+        assert_eq!(workgroups(256), 1);
+        assert_eq!(workgroups(257), 2);
+        assert_eq!(workgroups(100_000), 391);
+    }
+}
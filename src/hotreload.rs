@@ -0,0 +1,279 @@
+//notices edits to loaded files and shaders by polling their modification times, which needs no platform
+//support and costs next to nothing at a few polls per second
+use crate::gltfloader::GltfError;
+use crate::model::{InstanceData, Model, VertexData};
+use crate::objloader::{ObjError, Skipped};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug)]
+pub(crate) enum ReloadError {
+    Obj(ObjError),
+    Gltf(GltfError),
+    Parts {
+        path: PathBuf,
+        expected: usize,
+        found: usize,
+    },
+}
+impl std::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReloadError::Obj(e) => write!(f, "{}", e),
+            ReloadError::Gltf(e) => write!(f, "{}", e),
+            ReloadError::Parts {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} now has {} meshes instead of {}, restart to load it",
+                path.display(),
+                found,
+                expected
+            ),
+        }
+    }
+}
+impl std::error::Error for ReloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReloadError::Obj(e) => Some(e),
+            ReloadError::Gltf(e) => Some(e),
+            ReloadError::Parts { .. } => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum MeshFormat {
+    Obj,
+    Gltf,
+}
+
+//a mesh file and the models its groups or primitives became, in the order of the file
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MeshFile {
+    pub(crate) path: PathBuf,
+    pub(crate) format: MeshFormat,
+    pub(crate) models: Vec<usize>,
+}
+impl MeshFile {
+    //loads the file again and hands the meshes to the models, which keep their instances; on
+    //error the models are left alone. Returns the statements of an OBJ file that were skipped
+    pub(crate) fn reload(
+        &self,
+        models: &mut [Model<VertexData, InstanceData>],
+    ) -> Result<Vec<Skipped>, ReloadError> {
+        let (meshes, skipped) = match self.format {
+            MeshFormat::Obj => Model::object(&self.path).map_err(ReloadError::Obj)?,
+            //the nodes of the file are not looked at again, only the geometry
+            MeshFormat::Gltf => {
+                let meshes = Model::gltf_meshes(&self.path)
+                    .map_err(ReloadError::Gltf)?
+                    .into_iter()
+                    .map(|(model, _)| model)
+                    .collect();
+                (meshes, vec![])
+            }
+        };
+        if meshes.len() != self.models.len() {
+            return Err(ReloadError::Parts {
+                path: self.path.clone(),
+                expected: self.models.len(),
+                found: meshes.len(),
+            });
+        }
+        for (&index, mesh) in self.models.iter().zip(meshes) {
+            models[index].replace_mesh(mesh);
+        }
+        Ok(skipped)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum WatchedFile {
+    Mesh(MeshFile),
+    Scene(PathBuf),
+    Shader(PathBuf),
+}
+impl WatchedFile {
+    fn path(&self) -> &Path {
+        match self {
+            WatchedFile::Mesh(mesh) => &mesh.path,
+            WatchedFile::Scene(path) | WatchedFile::Shader(path) => path,
+        }
+    }
+}
+
+pub(crate) struct FileWatcher {
+    //with the modification time last seen
+    files: Vec<(WatchedFile, Option<SystemTime>)>,
+    interval: Duration,
+    last_poll: Instant,
+}
+impl FileWatcher {
+    pub(crate) fn new(interval: Duration) -> FileWatcher {
+        FileWatcher {
+            files: vec![],
+            interval,
+            last_poll: Instant::now(),
+        }
+    }
+    pub(crate) fn watch(&mut self, file: WatchedFile) {
+        let modified = modified(file.path());
+        self.files.push((file, modified));
+    }
+    //the files modified since they were watched or last returned; looks at most once per interval
+    //and lists scenes last, as reloading one moves the models of the meshes around
+    pub(crate) fn poll(&mut self) -> Vec<WatchedFile> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        let mut changed = vec![];
+        for (file, seen) in &mut self.files {
+            //a file that is missing is usually in the middle of being saved
+            if let Some(modified) = modified(file.path()) {
+                if *seen != Some(modified) {
+                    *seen = Some(modified);
+                    changed.push(file.clone());
+                }
+            }
+        }
+        changed.sort_by_key(|file| matches!(file, WatchedFile::Scene(_)));
+        changed
+    }
+    //for files the program wrote itself
+    pub(crate) fn mark_seen(&mut self, path: &Path) {
+        for (file, seen) in &mut self.files {
+            if file.path() == path {
+                *seen = modified(path);
+            }
+        }
+    }
+    //for when the models in `range` were replaced by `amount` others: forgets the files of the old
+    //models and moves the indices of the ones behind them
+    pub(crate) fn replace_models(&mut self, range: Range<usize>, amount: usize) {
+        self.files.retain(|(file, _)| match file {
+            WatchedFile::Mesh(mesh) => !mesh.models.iter().any(|m| range.contains(m)),
+            WatchedFile::Scene(_) | WatchedFile::Shader(_) => true,
+        });
+        for (file, _) in &mut self.files {
+            if let WatchedFile::Mesh(mesh) = file {
+                for m in mesh.models.iter_mut().filter(|m| **m >= range.end) {
+                    *m = *m - range.end + range.start + amount;
+                }
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("hotreload-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn touch(path: &Path, seconds: u64) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn polling_reports_each_change_once() {
+        let directory = temp_dir("poll");
+        let path = directory.join("scene.ron");
+        std::fs::write(&path, "()").unwrap();
+        touch(&path, 1000);
+        let mut watcher = FileWatcher::new(Duration::ZERO);
+        watcher.watch(WatchedFile::Scene(path.clone()));
+        let unchanged = watcher.poll();
+        touch(&path, 2000);
+        let changed = watcher.poll();
+        let again = watcher.poll();
+        touch(&path, 3000);
+        watcher.mark_seen(&path);
+        let seen = watcher.poll();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(unchanged.is_empty());
+        assert_eq!(changed, vec![WatchedFile::Scene(path)]);
+        assert!(again.is_empty());
+        assert!(seen.is_empty());
+    }
+
+    #[test]
+    fn replaced_models_move_the_others() {
+        let mesh = |models: Vec<usize>| {
+            WatchedFile::Mesh(MeshFile {
+                path: PathBuf::from("mesh.obj"),
+                format: MeshFormat::Obj,
+                models,
+            })
+        };
+        let mut watcher = FileWatcher::new(Duration::ZERO);
+        watcher.watch(mesh(vec![0, 1]));
+        watcher.watch(mesh(vec![2]));
+        watcher.watch(mesh(vec![3, 4]));
+        watcher.replace_models(0..3, 1);
+        let files: Vec<&WatchedFile> = watcher.files.iter().map(|(file, _)| file).collect();
+        assert_eq!(files, vec![&mesh(vec![1, 2])]);
+    }
+
+    #[test]
+    fn reloading_keeps_instances_and_the_last_good_mesh() {
+        let directory = temp_dir("reload");
+        let path = directory.join("mesh.obj");
+        std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let (mut models, _) = Model::object(&path).unwrap();
+        let handle = models[0].insert_visibly(InstanceData {
+            modelmatrix: [[0.0; 4]; 4],
+            colour: [1.0, 0.0, 0.0],
+        });
+        let file = MeshFile {
+            path: path.clone(),
+            format: MeshFormat::Obj,
+            models: vec![0],
+        };
+        std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 4 3\n").unwrap();
+        let reloaded = file.reload(&mut models);
+        std::fs::write(&path, "v 0 0 0\nf 1 2 3\n").unwrap();
+        let broken = file.reload(&mut models);
+        std::fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\ng second\nf 3 2 1\n",
+        )
+        .unwrap();
+        let split = file.reload(&mut models);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(reloaded.is_ok());
+        assert_eq!(models[0].get(handle).unwrap().colour, [1.0, 0.0, 0.0]);
+        assert!(matches!(broken, Err(ReloadError::Obj(_))));
+        assert!(matches!(
+            split,
+            Err(ReloadError::Parts {
+                expected: 1,
+                found: 2,
+                ..
+            })
+        ));
+    }
+}
@@ -1,11 +1,13 @@
 //contains the first steps of vulkan, namely instance, devices and queues
 
 use crate::debug::vulkan_debug_utils_callback;
+use crate::surface::Surface;
 use ash::{vk, Entry};
 
 pub(crate) fn init_instance(
     entry: &Entry,
     layer_names: &Vec<std::ffi::CString>,
+    surface_support: bool,
 ) -> Result<ash::Instance, vk::Result> {
     let app_name = std::ffi::CString::new("Jades Vulkan App").unwrap();
     let engine_name = std::ffi::CString::new("Jades Engine").unwrap();
@@ -19,11 +21,13 @@ pub(crate) fn init_instance(
         .iter()
         .map(|layer_name| layer_name.as_ptr())
         .collect();
-    let extension_name_pointers: Vec<*const i8> = vec![
-        ash::extensions::ext::DebugUtils::name().as_ptr(),
-        ash::extensions::khr::Surface::name().as_ptr(),
-        ash::extensions::khr::XlibSurface::name().as_ptr(),
-    ];
+    let mut extension_name_pointers: Vec<*const i8> =
+        vec![ash::extensions::ext::DebugUtils::name().as_ptr()];
+    //headless machines often have no windowing system, so only ask for surfaces when presenting
+    if surface_support {
+        extension_name_pointers.push(ash::extensions::khr::Surface::name().as_ptr());
+        extension_name_pointers.push(ash::extensions::khr::XlibSurface::name().as_ptr());
+    }
     let mut debug_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
         .message_severity(
             vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
@@ -46,19 +50,222 @@ pub(crate) fn init_instance(
     unsafe { Ok(entry.create_instance(&create_info, None)?) }
 }
 
-//TODO - This would not work on any integrated GPUs, also doesn't check for graphics support
+//a device forced by the user instead of the best scoring one
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DeviceChoice {
+    Index(usize),
+    //matched case-insensitively against any part of the device name
+    Name(String),
+}
+
+impl DeviceChoice {
+    pub(crate) const ENV_VAR: &'static str = "GRAPHICS_DEVICE";
+
+    pub(crate) fn parse(choice: &str) -> DeviceChoice {
+        match choice.trim().parse() {
+            Ok(index) => DeviceChoice::Index(index),
+            Err(_) => DeviceChoice::Name(choice.trim().to_string()),
+        }
+    }
+    pub(crate) fn from_env() -> Option<DeviceChoice> {
+        std::env::var(Self::ENV_VAR)
+            .ok()
+            .filter(|choice| !choice.trim().is_empty())
+            .map(|choice| Self::parse(&choice))
+    }
+    fn matches(&self, candidate: &DeviceCandidate) -> bool {
+        match self {
+            DeviceChoice::Index(index) => candidate.index == *index,
+            DeviceChoice::Name(name) => {
+                candidate.name.to_lowercase().contains(&name.to_lowercase())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeviceChoice::Index(index) => write!(f, "device #{}", index),
+            DeviceChoice::Name(name) => write!(f, "device named \"{}\"", name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum DeviceSelectionError {
+    Vulkan(vk::Result),
+    //every device the driver reported, with the reason it could not be used
+    NoSuitableDevice {
+        choice: Option<DeviceChoice>,
+        rejected: Vec<(String, String)>,
+    },
+}
+
+impl From<vk::Result> for DeviceSelectionError {
+    fn from(e: vk::Result) -> Self {
+        DeviceSelectionError::Vulkan(e)
+    }
+}
+
+impl std::fmt::Display for DeviceSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeviceSelectionError::Vulkan(e) => write!(f, "querying physical devices: {}", e),
+            DeviceSelectionError::NoSuitableDevice { choice, rejected } => {
+                match choice {
+                    Some(choice) => write!(f, "the requested {} is not usable", choice)?,
+                    None => write!(f, "no suitable physical device found")?,
+                }
+                if rejected.is_empty() {
+                    write!(f, " (the driver reported no devices)")?;
+                }
+                for (name, reason) in rejected {
+                    write!(f, "\n  {}: {}", name, reason)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeviceSelectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeviceSelectionError::Vulkan(e) => Some(e),
+            DeviceSelectionError::NoSuitableDevice { .. } => None,
+        }
+    }
+}
+
+//everything we learned about a device while deciding whether to use it
+struct DeviceCandidate {
+    index: usize,
+    name: String,
+    device_type: vk::PhysicalDeviceType,
+    has_graphics_queue: bool,
+    has_present_queue: bool,
+    missing_extensions: Vec<String>,
+    has_required_features: bool,
+}
+
+impl DeviceCandidate {
+    fn score(&self) -> Result<u32, String> {
+        if !self.has_graphics_queue {
+            return Err("no graphics queue family".to_string());
+        }
+        if !self.has_present_queue {
+            return Err("cannot present to the window surface".to_string());
+        }
+        if !self.missing_extensions.is_empty() {
+            return Err(format!(
+                "missing extensions {}",
+                self.missing_extensions.join(", ")
+            ));
+        }
+        if !self.has_required_features {
+            return Err("missing required device features".to_string());
+        }
+        Ok(match self.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 1000,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 500,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 200,
+            vk::PhysicalDeviceType::CPU => 100,
+            _ => 10,
+        })
+    }
+}
+
+//true if every feature switched on in `required` is also on in `available`
+fn supports_features(
+    available: &vk::PhysicalDeviceFeatures,
+    required: &vk::PhysicalDeviceFeatures,
+) -> bool {
+    //PhysicalDeviceFeatures is a repr(C) struct made up only of Bool32s
+    let count =
+        std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>();
+    let (available, required) = unsafe {
+        (
+            std::slice::from_raw_parts(available as *const _ as *const vk::Bool32, count),
+            std::slice::from_raw_parts(required as *const _ as *const vk::Bool32, count),
+        )
+    };
+    available
+        .iter()
+        .zip(required)
+        .all(|(&a, &r)| r == vk::FALSE || a == vk::TRUE)
+}
+
+//ranks every device by type after discarding those without graphics/present queues or without
+//the extensions and features we need; `choice` forces a specific device instead
 pub(crate) fn get_physical_device_and_properties(
     instance: &ash::Instance,
-) -> Result<(vk::PhysicalDevice, vk::PhysicalDeviceProperties), vk::Result> {
-    let mut chosen = None;
+    surface: Option<&Surface>,
+    required_extensions: &[&std::ffi::CStr],
+    required_features: &vk::PhysicalDeviceFeatures,
+    choice: Option<&DeviceChoice>,
+) -> Result<(vk::PhysicalDevice, vk::PhysicalDeviceProperties), DeviceSelectionError> {
     let phys_devs = unsafe { instance.enumerate_physical_devices()? };
-    for p in phys_devs {
+    let mut best: Option<(u32, vk::PhysicalDevice, vk::PhysicalDeviceProperties)> = None;
+    let mut rejected = vec![];
+    for (index, p) in phys_devs.into_iter().enumerate() {
         let properties = unsafe { instance.get_physical_device_properties(p) };
-        if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-            chosen = Some((p, properties));
+        let name = unsafe { std::ffi::CStr::from_ptr(properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        let has_graphics_queue = unsafe { instance.get_physical_device_queue_family_properties(p) }
+            .iter()
+            .any(|qfam| {
+                qfam.queue_count > 0 && qfam.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            });
+        let queue_families = QueueFamilies::init(instance, p, surface)?;
+        let available_extensions = unsafe { instance.enumerate_device_extension_properties(p)? };
+        let missing_extensions = required_extensions
+            .iter()
+            .filter(|required| {
+                !available_extensions.iter().any(|available| {
+                    let name =
+                        unsafe { std::ffi::CStr::from_ptr(available.extension_name.as_ptr()) };
+                    name == **required
+                })
+            })
+            .map(|missing| missing.to_string_lossy().into_owned())
+            .collect();
+        let available_features = unsafe { instance.get_physical_device_features(p) };
+        let candidate = DeviceCandidate {
+            index,
+            name,
+            device_type: properties.device_type,
+            has_graphics_queue,
+            has_present_queue: queue_families.graphics_q_index.is_some(),
+            missing_extensions,
+            has_required_features: supports_features(&available_features, required_features),
+        };
+        if let Some(choice) = choice {
+            if !choice.matches(&candidate) {
+                rejected.push((
+                    format!("#{} {}", index, candidate.name),
+                    format!("not the requested {}", choice),
+                ));
+                continue;
+            }
         }
+        match candidate.score() {
+            Ok(score) => {
+                if best.map_or(true, |(best_score, _, _)| score > best_score) {
+                    best = Some((score, p, properties));
+                }
+            }
+            Err(reason) => rejected.push((format!("#{} {}", index, candidate.name), reason)),
+        }
+    }
+    match best {
+        Some((_, p, properties)) => Ok((p, properties)),
+        None => Err(DeviceSelectionError::NoSuitableDevice {
+            choice: choice.cloned(),
+            rejected,
+        }),
     }
-    Ok(chosen.unwrap())
 }
 
 pub(crate) struct QueueFamilies {
@@ -70,14 +277,22 @@ impl QueueFamilies {
     pub(crate) fn init(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        //surfaces: &Surface,
+        surface: Option<&Surface>,
     ) -> Result<QueueFamilies, vk::Result> {
         let queuefamily_properties =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
         let mut found_graphics_q_index = None;
         let mut found_transfer_q_index = None;
         for (index, qfam) in queuefamily_properties.iter().enumerate() {
-            if qfam.queue_count > 0 && qfam.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+            //we present on the graphics queue, so with a surface it has to support both
+            let can_present = match surface {
+                Some(surface) => surface.get_present_support(physical_device, index as u32)?,
+                None => true,
+            };
+            if qfam.queue_count > 0
+                && qfam.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                && can_present
+            {
                 found_graphics_q_index = Some(index as u32);
             }
             if qfam.queue_count > 0 && qfam.queue_flags.contains(vk::QueueFlags::TRANSFER) {
@@ -97,7 +312,7 @@ impl QueueFamilies {
 
 pub(crate) struct Queues {
     pub(crate) graphics_queue: vk::Queue,
-    transfer_queue: vk::Queue,
+    pub(crate) transfer_queue: vk::Queue,
 }
 
 pub(crate) fn init_device_and_queues(
@@ -105,6 +320,8 @@ pub(crate) fn init_device_and_queues(
     physical_device: vk::PhysicalDevice,
     queue_families: &QueueFamilies,
     layer_names: &Vec<std::ffi::CString>,
+    extensions: &[&std::ffi::CStr],
+    features: &vk::PhysicalDeviceFeatures,
 ) -> Result<(ash::Device, Queues), vk::Result> {
     let layer_name_pointers: Vec<*const i8> = layer_names
         .iter()
@@ -112,21 +329,25 @@ pub(crate) fn init_device_and_queues(
         .collect();
 
     let priorities = [1.0f32];
-    let queue_info = [
-        vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(queue_families.graphics_q_index.unwrap())
-            .queue_priorities(&priorities)
-            .build(),
-        vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(queue_families.transfer_q_index.unwrap())
-            .queue_priorities(&priorities)
-            .build(),
-    ];
+    let mut queue_info = vec![vk::DeviceQueueCreateInfo::builder()
+        .queue_family_index(queue_families.graphics_q_index.unwrap())
+        .queue_priorities(&priorities)
+        .build()];
+    //devices without a dedicated transfer family share the graphics one, which may only be listed once
+    if queue_families.transfer_q_index != queue_families.graphics_q_index {
+        queue_info.push(
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(queue_families.transfer_q_index.unwrap())
+                .queue_priorities(&priorities)
+                .build(),
+        );
+    }
     let device_extension_name_pointers: Vec<*const i8> =
-        vec![ash::extensions::khr::Swapchain::name().as_ptr()];
+        extensions.iter().map(|name| name.as_ptr()).collect();
     let device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_info)
         .enabled_extension_names(&device_extension_name_pointers)
+        .enabled_features(features)
         .enabled_layer_names(&layer_name_pointers);
     let logical_device =
         unsafe { instance.create_device(physical_device, &device_create_info, None)? };
@@ -142,3 +363,70 @@ pub(crate) fn init_device_and_queues(
         },
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usable_candidate(device_type: vk::PhysicalDeviceType) -> DeviceCandidate {
+        DeviceCandidate {
+            index: 0,
+            name: "Test GPU".to_string(),
+            device_type,
+            has_graphics_queue: true,
+            has_present_queue: true,
+            missing_extensions: vec![],
+            has_required_features: true,
+        }
+    }
+
+    #[test]
+    fn discrete_beats_integrated_beats_cpu() {
+        let discrete = usable_candidate(vk::PhysicalDeviceType::DISCRETE_GPU).score();
+        let integrated = usable_candidate(vk::PhysicalDeviceType::INTEGRATED_GPU).score();
+        let cpu = usable_candidate(vk::PhysicalDeviceType::CPU).score();
+        assert!(discrete.unwrap() > integrated.clone().unwrap());
+        assert!(integrated.unwrap() > cpu.unwrap());
+    }
+
+    #[test]
+    fn unusable_devices_are_rejected_with_a_reason() {
+        let mut candidate = usable_candidate(vk::PhysicalDeviceType::DISCRETE_GPU);
+        candidate.has_present_queue = false;
+        assert_eq!(
+            candidate.score(),
+            Err("cannot present to the window surface".to_string())
+        );
+        candidate.has_present_queue = true;
+        candidate.missing_extensions = vec!["VK_KHR_swapchain".to_string()];
+        assert_eq!(
+            candidate.score(),
+            Err("missing extensions VK_KHR_swapchain".to_string())
+        );
+    }
+
+    #[test]
+    fn device_choice_parses_indices_and_names() {
+        assert_eq!(DeviceChoice::parse("1"), DeviceChoice::Index(1));
+        assert_eq!(
+            DeviceChoice::parse(" llvmpipe "),
+            DeviceChoice::Name("llvmpipe".to_string())
+        );
+        let candidate = usable_candidate(vk::PhysicalDeviceType::CPU);
+        assert!(DeviceChoice::parse("test gpu").matches(&candidate));
+        assert!(DeviceChoice::parse("0").matches(&candidate));
+        assert!(!DeviceChoice::parse("2").matches(&candidate));
+    }
+
+    #[test]
+    fn feature_check_only_looks_at_required_features() {
+        let mut available = vk::PhysicalDeviceFeatures::default();
+        let mut required = vk::PhysicalDeviceFeatures::default();
+        assert!(supports_features(&available, &required));
+        required.fill_mode_non_solid = vk::TRUE;
+        assert!(!supports_features(&available, &required));
+        available.fill_mode_non_solid = vk::TRUE;
+        available.wide_lines = vk::TRUE;
+        assert!(supports_features(&available, &required));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::buffer::{BufferError, TypedBuffer};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Light {
+    //`direction` is the way the light travels, it does not need to be normalised
+    Directional {
+        direction: [f32; 3],
+        colour: [f32; 3],
+    },
+    //falls off with 1/(1+distance²)
+    Point {
+        position: [f32; 3],
+        colour: [f32; 3],
+    },
+}
+
+//what the shaders see of a light, following std140 rules
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+struct LightUniform {
+    //w is 0 for directional lights and 1 for point lights
+    position: [f32; 4],
+    colour: [f32; 4],
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub(crate) struct LightingUniform {
+    ambient: [f32; 4],
+    amount: [u32; 4],
+    lights: [LightUniform; Lighting::MAX_LIGHTS],
+}
+
+//only 4-byte fields in multiples of 16 bytes, so there is no padding
+unsafe impl bytemuck::Zeroable for LightUniform {}
+unsafe impl bytemuck::Pod for LightUniform {}
+unsafe impl bytemuck::Zeroable for LightingUniform {}
+unsafe impl bytemuck::Pod for LightingUniform {}
+
+//edit freely between frames, `update_buffer` hands the current state to the GPU
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Lighting {
+    pub(crate) ambient: [f32; 3],
+    pub(crate) lights: Vec<Light>,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting {
+            ambient: [0.2, 0.2, 0.2],
+            lights: vec![Light::Directional {
+                direction: [-0.3, 1.0, 0.5],
+                colour: [0.8, 0.8, 0.8],
+            }],
+        }
+    }
+}
+
+impl Lighting {
+    //has to match MAX_LIGHTS in shader.frag
+    pub(crate) const MAX_LIGHTS: usize = 16;
+    pub(crate) const BUFFER_SIZE: u64 = std::mem::size_of::<LightingUniform>() as u64;
+
+    fn uniform(&self) -> Result<LightingUniform, BufferError> {
+        if self.lights.len() > Self::MAX_LIGHTS {
+            return Err(BufferError::OutOfBounds {
+                requested: (32 + 32 * self.lights.len()) as u64,
+                capacity: Self::BUFFER_SIZE,
+            });
+        }
+        let [r, g, b] = self.ambient;
+        let mut uniform = LightingUniform {
+            ambient: [r, g, b, 0.0],
+            amount: [self.lights.len() as u32, 0, 0, 0],
+            lights: [LightUniform::default(); Self::MAX_LIGHTS],
+        };
+        for (slot, light) in uniform.lights.iter_mut().zip(&self.lights) {
+            *slot = match *light {
+                Light::Directional {
+                    direction: [x, y, z],
+                    colour: [r, g, b],
+                } => LightUniform {
+                    position: [x, y, z, 0.0],
+                    colour: [r, g, b, 0.0],
+                },
+                Light::Point {
+                    position: [x, y, z],
+                    colour: [r, g, b],
+                } => LightUniform {
+                    position: [x, y, z, 1.0],
+                    colour: [r, g, b, 0.0],
+                },
+            };
+        }
+        Ok(uniform)
+    }
+    pub(crate) fn update_buffer(
+        &self,
+        allocator: &vk_mem::Allocator,
+        buffer: &mut TypedBuffer<LightingUniform>,
+    ) -> Result<(), BufferError> {
+        let uniform = self.uniform()?;
+        buffer.write(allocator, 0, &[uniform])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_follows_std140() {
+        assert_eq!(std::mem::size_of::<LightUniform>(), 32);
+        assert_eq!(Lighting::BUFFER_SIZE, 32 + 32 * Lighting::MAX_LIGHTS as u64);
+        let lighting = Lighting {
+            ambient: [0.1, 0.2, 0.3],
+            lights: vec![Light::Point {
+                position: [1.0, 2.0, 3.0],
+                colour: [1.0, 1.0, 1.0],
+            }],
+        };
+        let uniform = lighting.uniform().unwrap();
+        assert_eq!(uniform.amount[0], 1);
+        assert_eq!(uniform.lights[0].position, [1.0, 2.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn too_many_lights_are_rejected() {
+        let lighting = Lighting {
+            ambient: [0.0; 3],
+            lights: vec![Lighting::default().lights[0]; Lighting::MAX_LIGHTS + 1],
+        };
+        assert!(matches!(
+            lighting.uniform(),
+            Err(BufferError::OutOfBounds { .. })
+        ));
+    }
+}
@@ -1,99 +1,232 @@
 use crate::camera::Camera;
-use crate::model::{InstanceData, Model};
-use crate::swapchain::Swapchain;
-use crate::vkinterface::VkInterface;
+use crate::hotreload::{FileWatcher, MeshFile, MeshFormat, WatchedFile};
+use crate::initialization::DeviceChoice;
+use crate::lights::{Light, Lighting};
+use crate::model::{ColouredVertex, InstanceData, Model, Transform, VertexData};
+use crate::objloader::Skipped;
+use crate::rendering::{BlendMode, PipelineState};
+use crate::scene::{BuiltScene, Placements, SceneDescription};
+use crate::shaders::ShaderSet;
+use crate::vkinterface::{RendererConfig, VkInterface};
 use ash::vk;
 use nalgebra as na;
 
 mod buffer;
 mod camera;
 mod commandbuffers;
+mod culling;
 mod debug;
+mod frames;
+mod gltfloader;
+#[cfg(test)]
+mod golden;
+mod gpuculling;
+mod hotreload;
 mod initialization;
+mod lights;
+mod mesh;
 mod model;
+mod modelgroup;
+mod objloader;
+mod offscreen;
+mod pipelinecache;
+mod primitives;
+mod reflection;
 mod rendering;
+mod scene;
+mod shaders;
+mod slotmap;
 mod surface;
 mod swapchain;
+mod upload;
+mod vertexlayout;
 mod vkinterface;
 
 //to.dos show important notes of things that could be improved
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    //`--headless [file.png]` renders a single frame without opening a window
+    //`--device <index or name>` picks the GPU, taking precedence over GRAPHICS_DEVICE
+    //`--frames-in-flight <n>` sets how far the CPU may run ahead of the GPU
+    //`--gpu-culling` culls the instances in a compute shader instead of on the CPU
+    //`--gltf <file>` shows the scene in a .gltf or .glb file instead of the example scene
+    //`--scene <file.ron>` shows a scene description instead, and F5 saves the current state back to it
+    //the files of the shown scene and meshes are reloaded whenever they change on disk, and the
+    //shaders in `--shaders <directory>` (by default shaders/) recompiled
+    let args: Vec<String> = std::env::args().collect();
+    let mut config = RendererConfig {
+        device_choice: args
+            .iter()
+            .position(|arg| arg == "--device")
+            .and_then(|position| args.get(position + 1))
+            .map(|choice| DeviceChoice::parse(choice)),
+        gpu_culling: args.iter().any(|arg| arg == "--gpu-culling"),
+        ..Default::default()
+    };
+    if let Some(directory) = args
+        .iter()
+        .position(|arg| arg == "--shaders")
+        .and_then(|position| args.get(position + 1))
+    {
+        config.shader_directory = directory.into();
+    }
+    if let Some(frames) = args
+        .iter()
+        .position(|arg| arg == "--frames-in-flight")
+        .and_then(|position| args.get(position + 1))
+    {
+        config.frames_in_flight = frames.parse()?;
+    }
+    let scene_path = args
+        .iter()
+        .position(|arg| arg == "--scene")
+        .and_then(|position| args.get(position + 1))
+        .map(std::path::PathBuf::from);
+    let mut description = match &scene_path {
+        Some(path) => SceneDescription::load(path)?,
+        None => SceneDescription::default(),
+    };
+    let gltf_path = args
+        .iter()
+        .position(|arg| arg == "--gltf")
+        .and_then(|position| args.get(position + 1));
+    let (mut models, mut placements, mut mesh_files) = match (&scene_path, gltf_path) {
+        (Some(path), _) => {
+            let BuiltScene {
+                models,
+                placements,
+                mesh_files,
+                skipped,
+            } = description.build(scene_directory(path))?;
+            report_skipped(&skipped);
+            (models, placements, mesh_files)
+        }
+        (None, Some(path)) => {
+            let models = Model::gltf(path)?;
+            let mesh_file = MeshFile {
+                path: path.into(),
+                format: MeshFormat::Gltf,
+                models: (0..models.len()).collect(),
+            };
+            (models, vec![], vec![mesh_file])
+        }
+        (None, None) => (example_scene(), vec![], vec![]),
+    };
+    //the models of the scene come first, anything added later goes behind them
+    let mut scene_models = models.len();
+    if let Some(position) = args.iter().position(|arg| arg == "--headless") {
+        let path = args
+            .get(position + 1)
+            .filter(|arg| !arg.starts_with("--"))
+            .map(String::as_str)
+            .unwrap_or("render.png");
+        return render_headless(
+            path,
+            800,
+            600,
+            config,
+            models,
+            description.camera(),
+            description.clear_colour,
+        );
+    }
+
     let eventloop = winit::event_loop::EventLoop::new();
     let window = winit::window::Window::new(&eventloop)?;
-    let mut vk_struct = VkInterface::init(window)?;
+    let mut vk_struct = VkInterface::init(window, config)?;
 
-    let mut cube = Model::cube();
-    let mut object = Model::object("squirrel.obj");
-    object.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.1, 0.2, 0.4))
-            * na::Matrix4::new_scaling(0.01))
-        .into(),
-        colour: [0.0, 0.0, 1.0],
-    });
-    cube.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.0, 0.1))
-            * na::Matrix4::new_scaling(0.1))
-        .into(),
-        colour: [0.2, 0.4, 1.0],
-    });
-    cube.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.05, 0.05, 0.0))
-            * na::Matrix4::new_scaling(0.1))
-        .into(),
-        colour: [1.0, 1.0, 0.2],
-    });
-    for i in 0..10 {
-        for j in 0..10 {
-            cube.insert_visibly(InstanceData {
-                modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(
-                    i as f32 * 0.2 - 1.0,
-                    j as f32 * 0.2 - 1.0,
-                    0.5,
-                )) * na::Matrix4::new_scaling(0.03))
-                .into(),
-                colour: [1.0, i as f32 * 0.07, j as f32 * 0.07],
+    for model in &mut models {
+        model.update_vertexbuffer(&vk_struct.allocator, &mut vk_struct.uploader)?;
+    }
+    //the instances follow with every frame, into that frame's own instance buffers
+    vk_struct.models = models;
+    match Model::object("squirrel.obj") {
+        Ok((objects, skipped)) => {
+            for statement in skipped {
+                eprintln!("squirrel.obj: {}", statement);
+            }
+            mesh_files.push(MeshFile {
+                path: "squirrel.obj".into(),
+                format: MeshFormat::Obj,
+                models: (0..objects.len())
+                    .map(|i| vk_struct.models.len() + i)
+                    .collect(),
             });
-            cube.insert_visibly(InstanceData {
-                modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(
-                    i as f32 * 0.2 - 1.0,
-                    0.0,
-                    j as f32 * 0.2 - 1.0,
-                )) * na::Matrix4::new_scaling(0.02))
-                .into(),
-                colour: [i as f32 * 0.07, j as f32 * 0.07, 1.0],
+            for mut object in objects {
+                object.insert_visibly(InstanceData {
+                    modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.1, 0.2, 0.4))
+                        * na::Matrix4::new_scaling(0.01))
+                    .into(),
+                    colour: [0.0, 0.0, 1.0],
+                });
+                object.update_vertexbuffer(&vk_struct.allocator, &mut vk_struct.uploader)?;
+                vk_struct.models.push(object);
+            }
+        }
+        Err(e) => eprintln!("{}", e),
+    }
+    for mut model in pipeline_showcase() {
+        model.update_vertexbuffer(&vk_struct.allocator, &mut vk_struct.uploader)?;
+        vk_struct.models.push(model);
+    }
+    //a triangle coloured per vertex, drawn through a model group with shaders of its own
+    let vertexcolour_shaders = vk_struct.shader_directory().join("vertexcolour");
+    match vk_struct.add_model_group::<ColouredVertex, Transform>(vertexcolour_shaders.clone()) {
+        Ok(index) => {
+            let corners = [
+                ColouredVertex {
+                    position: [-0.5, 0.0, 0.0],
+                    colour: [1.0, 0.0, 0.0],
+                },
+                ColouredVertex {
+                    position: [0.5, 0.0, 0.0],
+                    colour: [0.0, 1.0, 0.0],
+                },
+                ColouredVertex {
+                    position: [0.0, -0.8, 0.0],
+                    colour: [0.0, 0.0, 1.0],
+                },
+            ];
+            let mut triangle = Model::indexed(&corners, &[0, 1, 2]);
+            triangle.insert_visibly(Transform {
+                modelmatrix: na::Matrix4::new_translation(&na::Vector3::new(-1.5, 0.0, 0.0)).into(),
+            });
+            //and its outline as lines
+            let mut outline = Model::indexed(&corners, &[0, 1, 1, 2, 2, 0]);
+            outline.insert_visibly(Transform {
+                modelmatrix: na::Matrix4::new_translation(&na::Vector3::new(-1.5, 0.0, -0.3))
+                    .into(),
             });
+            outline.set_pipeline_state(PipelineState::lines());
+            if let Some(group) = vk_struct.model_group::<ColouredVertex, Transform>(index) {
+                group.models.push(triangle);
+                group.models.push(outline);
+            }
+            vk_struct.update_model_group_vertexbuffers(index)?;
         }
+        Err(e) => eprintln!("{}", e),
     }
-    cube.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::from_scaled_axis(na::Vector3::new(0.0, 0.0, 1.4))
-            * na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.5, 0.0))
-            * na::Matrix4::new_scaling(0.1))
-        .into(),
-        colour: [0.0, 0.5, 0.0],
-    });
-    cube.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.5, 0.0, 0.0))
-            * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(0.5, 0.01, 0.01)))
-        .into(),
-        colour: [1.0, 0.5, 0.5],
-    });
-    cube.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.5, 0.0))
-            * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(0.01, 0.5, 0.01)))
-        .into(),
-        colour: [0.5, 1.0, 0.5],
-    });
-    cube.insert_visibly(InstanceData {
-        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.0, 0.0))
-            * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(0.01, 0.01, 0.5)))
-        .into(),
-        colour: [0.5, 0.5, 1.0],
-    });
-    cube.update_vertexbuffer(&vk_struct.allocator).unwrap();
-    cube.update_instancebuffer(&vk_struct.allocator).unwrap();
-    vk_struct.models = vec![cube];
 
-    let mut camera = Camera::default();
+    let extent = vk_struct.swapchain.as_ref().unwrap().extent;
+    let mut camera = description.camera();
+    camera.set_aspect(extent.width as f32 / extent.height as f32);
+    vk_struct.clear_colour = description.clear_colour;
+    let mut lighting = Lighting::default();
+    let mut swapchain_outdated = false;
+    //shown in the window title at most once a second, to see what culling saves
+    let mut last_culled = 0;
+    let mut culled_reported = std::time::Instant::now();
+    let mut watcher = FileWatcher::new(std::time::Duration::from_millis(500));
+    for mesh_file in mesh_files {
+        watcher.watch(WatchedFile::Mesh(mesh_file));
+    }
+    if let Some(path) = &scene_path {
+        watcher.watch(WatchedFile::Scene(path.clone()));
+    }
+    for directory in [vk_struct.shader_directory(), &vertexcolour_shaders] {
+        for shader in [ShaderSet::VERTEX, ShaderSet::FRAGMENT] {
+            watcher.watch(WatchedFile::Shader(directory.join(shader)));
+        }
+    }
 
     use winit::event::{Event, WindowEvent};
     eventloop.run(move |event, _, controlflow| match event {
@@ -103,6 +236,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         } => {
             *controlflow = winit::event_loop::ControlFlow::Exit;
         }
+        Event::WindowEvent {
+            event: WindowEvent::Resized(_),
+            ..
+        } => {
+            swapchain_outdated = true;
+        }
         Event::WindowEvent {
             event: WindowEvent::KeyboardInput { input, .. },
             ..
@@ -132,59 +271,187 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     winit::event::VirtualKeyCode::PageDown => {
                         camera.turn_down(0.02);
                     }
+                    //drops a point light where the camera is, Back removes the newest one
+                    winit::event::VirtualKeyCode::L
+                        if lighting.lights.len() < Lighting::MAX_LIGHTS =>
+                    {
+                        lighting.lights.push(Light::Point {
+                            position: camera.position(),
+                            colour: [1.0, 0.9, 0.7],
+                        });
+                    }
+                    winit::event::VirtualKeyCode::Back => {
+                        lighting.lights.pop();
+                    }
+                    winit::event::VirtualKeyCode::F5 => {
+                        if let Some(path) = &scene_path {
+                            description.capture(
+                                &vk_struct.models,
+                                &placements,
+                                &camera,
+                                vk_struct.clear_colour,
+                            );
+                            match description.save(path) {
+                                Ok(()) => println!("saved the scene to {}", path.display()),
+                                Err(e) => eprintln!("{}", e),
+                            }
+                            watcher.mark_seen(path);
+                        }
+                    }
                     _ => {}
                 }
             }
         }
         Event::MainEventsCleared => {
-            vk_struct.window.request_redraw();
+            //a minimised window has nothing to draw into, so sleep until the next event
+            let window = vk_struct.window.as_ref().unwrap();
+            let size = window.inner_size();
+            if size.width == 0 || size.height == 0 {
+                *controlflow = winit::event_loop::ControlFlow::Wait;
+            } else {
+                *controlflow = winit::event_loop::ControlFlow::Poll;
+                window.request_redraw();
+            }
         }
         Event::RedrawRequested(_) => {
-            let (image_index, _) = unsafe {
-                vk_struct
-                    .swapchain
-                    .swapchain_loader
-                    .acquire_next_image(
-                        vk_struct.swapchain.swapchain,
-                        u64::MAX,
-                        vk_struct.swapchain.image_available[vk_struct.swapchain.current_image],
-                        vk::Fence::null(),
-                    )
-                    .expect("image acquisition trouble")
-            };
+            if swapchain_outdated {
+                if !vk_struct
+                    .recreate_swapchain()
+                    .expect("swapchain recreation")
+                {
+                    return;
+                }
+                let extent = vk_struct.swapchain.as_ref().unwrap().extent;
+                camera.set_aspect(extent.width as f32 / extent.height as f32);
+                swapchain_outdated = false;
+            }
+            let frame = &vk_struct.frames[vk_struct.current_frame];
+            let image_available = frame.image_available;
+            let rendering_finished = frame.rendering_finished;
+            let may_begin_drawing = frame.may_begin_drawing;
+            //the GPU must be done with this frame's command buffer and uniform buffer
             unsafe {
                 vk_struct
                     .device
-                    .wait_for_fences(
-                        &[
-                            vk_struct.swapchain.may_begin_drawing
-                                [vk_struct.swapchain.current_image],
-                        ],
-                        true,
-                        u64::MAX,
-                    )
+                    .wait_for_fences(&[may_begin_drawing], true, u64::MAX)
                     .expect("fence-waiting");
+                vk_struct.frames[vk_struct.current_frame]
+                    .free_retired_buffers(&vk_struct.allocator);
+            }
+            //errors keep the last good state on screen
+            for file in watcher.poll() {
+                match file {
+                    WatchedFile::Mesh(mesh) => match mesh.reload(&mut vk_struct.models) {
+                        Ok(skipped) => {
+                            for statement in skipped {
+                                eprintln!("{}: {}", mesh.path.display(), statement);
+                            }
+                            vk_struct
+                                .update_vertexbuffers(&mesh.models)
+                                .expect("updating the vertex buffers");
+                            println!("reloaded {}", mesh.path.display());
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    },
+                    WatchedFile::Scene(path) => {
+                        match reload_scene(&path, &mut vk_struct, &mut watcher, &mut scene_models) {
+                            Ok((reloaded, placed)) => {
+                                description = reloaded;
+                                placements = placed;
+                                let extent = vk_struct.swapchain.as_ref().unwrap().extent;
+                                camera = description.camera();
+                                camera.set_aspect(extent.width as f32 / extent.height as f32);
+                                vk_struct.clear_colour = description.clear_colour;
+                                println!("reloaded {}", path.display());
+                            }
+                            Err(e) => eprintln!("{}", e),
+                        }
+                    }
+                    WatchedFile::Shader(path) => match vk_struct.reload_shaders() {
+                        Ok(()) => {
+                            println!("recompiled the shaders after {} changed", path.display())
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    },
+                }
+            }
+            //models whose pipeline cannot be built are left out
+            if let Err(e) = vk_struct.prepare_pipelines() {
+                eprintln!("{}", e);
+            }
+            let swapchain = vk_struct.swapchain.as_mut().unwrap();
+            let image_index = match unsafe {
+                swapchain.swapchain_loader.acquire_next_image(
+                    swapchain.swapchain,
+                    u64::MAX,
+                    image_available,
+                    vk::Fence::null(),
+                )
+            } {
+                Ok((image_index, suboptimal)) => {
+                    swapchain_outdated |= suboptimal;
+                    image_index
+                }
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    swapchain_outdated = true;
+                    return;
+                }
+                Err(e) => panic!("image acquisition trouble: {}", e),
+            };
+            //with more images than frames in flight, an older frame may still be drawing into this image
+            let image_in_flight = swapchain.images_in_flight[image_index as usize];
+            if image_in_flight != vk::Fence::null() && image_in_flight != may_begin_drawing {
+                unsafe {
+                    vk_struct
+                        .device
+                        .wait_for_fences(&[image_in_flight], true, u64::MAX)
+                        .expect("fence-waiting");
+                }
+            }
+            swapchain.images_in_flight[image_index as usize] = may_begin_drawing;
+            //only reset once we are sure to submit, otherwise the next wait would never return
+            unsafe {
                 vk_struct
                     .device
-                    .reset_fences(&[
-                        vk_struct.swapchain.may_begin_drawing[vk_struct.swapchain.current_image]
-                    ])
+                    .reset_fences(&[may_begin_drawing])
                     .expect("resetting fences");
             }
-            camera.update_buffer(&vk_struct.allocator, &mut vk_struct.uniformbuffer);
-            for m in &mut vk_struct.models {
-                m.update_instancebuffer(&vk_struct.allocator).unwrap();
+            camera
+                .update_buffer(
+                    &vk_struct.allocator,
+                    &mut vk_struct.frames[vk_struct.current_frame].uniformbuffer,
+                )
+                .expect("updating the camera");
+            lighting
+                .update_buffer(
+                    &vk_struct.allocator,
+                    &mut vk_struct.frames[vk_struct.current_frame].lightbuffer,
+                )
+                .expect("updating lights");
+            let culled = vk_struct
+                .update_instancebuffers(&camera.frustum())
+                .expect("updating the instance buffers");
+            if culled != last_culled && culled_reported.elapsed().as_secs() >= 1 {
+                if let Some(window) = &vk_struct.window {
+                    window.set_title(&format!("{} instances culled", culled));
+                }
+                last_culled = culled;
+                culled_reported = std::time::Instant::now();
             }
             vk_struct
                 .update_commandbuffer(image_index as usize)
                 .expect("updating the command buffer");
 
-            let semaphores_available =
-                [vk_struct.swapchain.image_available[vk_struct.swapchain.current_image]];
-            let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-            let semaphores_finished =
-                [vk_struct.swapchain.rendering_finished[vk_struct.swapchain.current_image]];
-            let commandbuffers = [vk_struct.command_buffers[image_index as usize]];
+            //vertex input waits for the uploads sent off with the command buffer
+            let mut semaphores_available = vec![image_available];
+            let mut waiting_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+            if let Some(upload_finished) = vk_struct.frames[vk_struct.current_frame].upload_finished
+            {
+                semaphores_available.push(upload_finished);
+                waiting_stages.push(vk::PipelineStageFlags::VERTEX_INPUT);
+            }
+            let semaphores_finished = [rendering_finished];
+            let commandbuffers = [vk_struct.frames[vk_struct.current_frame].commandbuffer];
             let submit_info = [vk::SubmitInfo::builder()
                 .wait_semaphores(&semaphores_available)
                 .wait_dst_stage_mask(&waiting_stages)
@@ -197,26 +464,189 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .queue_submit(
                         vk_struct.queues.graphics_queue,
                         &submit_info,
-                        vk_struct.swapchain.may_begin_drawing[vk_struct.swapchain.current_image],
+                        may_begin_drawing,
                     )
                     .expect("queue submission");
             };
-            let swapchains = [vk_struct.swapchain.swapchain];
+            let swapchain = vk_struct.swapchain.as_mut().unwrap();
+            let swapchains = [swapchain.swapchain];
             let indices = [image_index];
             let present_info = vk::PresentInfoKHR::builder()
                 .wait_semaphores(&semaphores_finished)
                 .swapchains(&swapchains)
                 .image_indices(&indices);
-            unsafe {
-                vk_struct
-                    .swapchain
+            match unsafe {
+                swapchain
                     .swapchain_loader
                     .queue_present(vk_struct.queues.graphics_queue, &present_info)
-                    .expect("queue presentation");
-            };
-            vk_struct.swapchain.current_image = (vk_struct.swapchain.current_image + 1)
-                % vk_struct.swapchain.amount_of_images as usize;
+            } {
+                Ok(suboptimal) => swapchain_outdated |= suboptimal,
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => swapchain_outdated = true,
+                Err(e) => panic!("queue presentation: {}", e),
+            }
+            vk_struct.current_frame = (vk_struct.current_frame + 1) % vk_struct.frames.len();
         }
         _ => {}
     });
 }
+
+//mesh files named in a scene file are relative to it
+fn scene_directory(path: &std::path::Path) -> &std::path::Path {
+    path.parent().unwrap_or(std::path::Path::new(""))
+}
+
+//OBJ statements the loader left out are not errors, but may explain a model that looks wrong
+fn report_skipped(skipped: &[(std::path::PathBuf, Skipped)]) {
+    for (path, statement) in skipped {
+        eprintln!("{}: {}", path.display(), statement);
+    }
+}
+
+//builds the scene in `path` again in place of the models it built before, which stay untouched if
+//the file has errors
+fn reload_scene(
+    path: &std::path::Path,
+    vk_struct: &mut VkInterface,
+    watcher: &mut FileWatcher,
+    scene_models: &mut usize,
+) -> Result<(SceneDescription, Placements), Box<dyn std::error::Error>> {
+    let description = SceneDescription::load(path)?;
+    let BuiltScene {
+        models,
+        placements,
+        mesh_files,
+        skipped,
+    } = description.build(scene_directory(path))?;
+    report_skipped(&skipped);
+    let amount = models.len();
+    vk_struct.replace_models(0..*scene_models, models)?;
+    watcher.replace_models(0..*scene_models, amount);
+    for mesh_file in mesh_files {
+        watcher.watch(WatchedFile::Mesh(mesh_file));
+    }
+    *scene_models = amount;
+    Ok((description, placements))
+}
+
+fn render_headless(
+    path: &str,
+    width: u32,
+    height: u32,
+    config: RendererConfig,
+    mut models: Vec<Model<VertexData, InstanceData>>,
+    mut camera: Camera,
+    clear_colour: [f32; 4],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut vk_struct = VkInterface::init_headless(width, height, config)?;
+    vk_struct.clear_colour = clear_colour;
+    for model in &mut models {
+        model.update_vertexbuffer(&vk_struct.allocator, &mut vk_struct.uploader)?;
+        model.update_instancebuffer(&vk_struct.allocator, vk_struct.current_frame)?;
+    }
+    vk_struct.models = models;
+
+    camera.set_aspect(width as f32 / height as f32);
+    camera.update_buffer(&vk_struct.allocator, &mut vk_struct.frames[0].uniformbuffer)?;
+    Lighting::default()
+        .update_buffer(&vk_struct.allocator, &mut vk_struct.frames[0].lightbuffer)?;
+    let pixels = vk_struct.render_offscreen()?;
+    offscreen::write_png(path, vk::Extent2D { width, height }, &pixels)
+}
+
+//the cube grid and axes shown by default
+fn example_scene() -> Vec<Model<VertexData, InstanceData>> {
+    let mut cube = Model::cube();
+    cube.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.0, 0.1))
+            * na::Matrix4::new_scaling(0.1))
+        .into(),
+        colour: [0.2, 0.4, 1.0],
+    });
+    cube.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.05, 0.05, 0.0))
+            * na::Matrix4::new_scaling(0.1))
+        .into(),
+        colour: [1.0, 1.0, 0.2],
+    });
+    for i in 0..10 {
+        for j in 0..10 {
+            cube.insert_visibly(InstanceData {
+                modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(
+                    i as f32 * 0.2 - 1.0,
+                    j as f32 * 0.2 - 1.0,
+                    0.5,
+                )) * na::Matrix4::new_scaling(0.03))
+                .into(),
+                colour: [1.0, i as f32 * 0.07, j as f32 * 0.07],
+            });
+            cube.insert_visibly(InstanceData {
+                modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(
+                    i as f32 * 0.2 - 1.0,
+                    0.0,
+                    j as f32 * 0.2 - 1.0,
+                )) * na::Matrix4::new_scaling(0.02))
+                .into(),
+                colour: [i as f32 * 0.07, j as f32 * 0.07, 1.0],
+            });
+        }
+    }
+    cube.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::from_scaled_axis(na::Vector3::new(0.0, 0.0, 1.4))
+            * na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.5, 0.0))
+            * na::Matrix4::new_scaling(0.1))
+        .into(),
+        colour: [0.0, 0.5, 0.0],
+    });
+    //the x, y and z axes
+    let mut arrow = Model::arrow(16);
+    for (rotation, colour) in [
+        (
+            na::Vector3::new(0.0, 0.0, -std::f32::consts::FRAC_PI_2),
+            [1.0, 0.5, 0.5],
+        ),
+        (na::Vector3::zeros(), [0.5, 1.0, 0.5]),
+        (
+            na::Vector3::new(std::f32::consts::FRAC_PI_2, 0.0, 0.0),
+            [0.5, 0.5, 1.0],
+        ),
+    ] {
+        arrow.insert_visibly(InstanceData {
+            modelmatrix: na::Matrix4::from_scaled_axis(rotation).into(),
+            colour,
+        });
+    }
+    vec![cube, arrow]
+}
+
+//a wireframe, a point cloud and a glowing see-through sphere, each drawn with another pipeline
+fn pipeline_showcase() -> Vec<Model<VertexData, InstanceData>> {
+    let mut wireframe = Model::torus(24, 12, 0.3);
+    wireframe.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(1.0, -0.5, 0.0))
+            * na::Matrix4::new_scaling(0.3))
+        .into(),
+        colour: [1.0, 0.6, 0.2],
+    });
+    wireframe.set_pipeline_state(PipelineState::wireframe());
+    let mut points = Model::icosphere(2);
+    points.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(-1.0, -0.5, 0.0))
+            * na::Matrix4::new_scaling(0.3))
+        .into(),
+        colour: [1.0, 1.0, 1.0],
+    });
+    points.set_pipeline_state(PipelineState::points());
+    let mut glass = Model::icosphere(3);
+    glass.insert_visibly(InstanceData {
+        modelmatrix: (na::Matrix4::new_translation(&na::Vector3::new(0.0, -0.5, 0.4))
+            * na::Matrix4::new_scaling(0.25))
+        .into(),
+        colour: [0.6, 0.8, 1.0],
+    });
+    //the shaders give every fragment an alpha of 1, so alpha blending would look opaque
+    glass.set_pipeline_state(PipelineState {
+        blend: BlendMode::Additive,
+        ..PipelineState::transparent()
+    });
+    vec![wireframe, points, glass]
+}
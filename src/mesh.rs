@@ -0,0 +1,240 @@
+//processing of indexed triangle meshes before they become models
+use nalgebra as na;
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum NormalMode {
+    //every triangle gets its own face normal, giving hard edges everywhere
+    Flat,
+    //corners at the same position share the angle-weighted average of the faces around them,
+    //except across edges sharper than `crease_angle` (in radians)
+    Smooth { crease_angle: f32 },
+}
+
+//normals, texcoords and tangents are either empty or as long as positions; tangents carry the
+//handedness of the bitangent in w
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Mesh {
+    pub(crate) positions: Vec<[f32; 3]>,
+    pub(crate) normals: Vec<[f32; 3]>,
+    pub(crate) texcoords: Vec<[f32; 2]>,
+    pub(crate) tangents: Vec<[f32; 4]>,
+    pub(crate) indices: Vec<u32>,
+}
+
+fn vector(p: [f32; 3]) -> na::Vector3<f32> {
+    na::Vector3::new(p[0], p[1], p[2])
+}
+
+//adding zero turns -0.0 into 0.0, which would otherwise count as a different value
+fn bits<const N: usize>(values: [f32; N]) -> [u32; N] {
+    values.map(|value| (value + 0.0).to_bits())
+}
+
+type VertexKey = ([u32; 3], [u32; 3], [u32; 2]);
+
+impl Mesh {
+    //replaces any existing normals; vertices are split wherever a corner needs a different normal
+    //than its neighbours, and tangents are rebuilt if there are texcoords
+    pub(crate) fn with_generated_normals(&self, mode: NormalMode) -> Mesh {
+        let triangles: Vec<[usize; 3]> = self
+            .indices
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+            .collect();
+        let face_normals: Vec<na::Vector3<f32>> = triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let (a, b, c) = (
+                    vector(self.positions[a]),
+                    vector(self.positions[b]),
+                    vector(self.positions[c]),
+                );
+                (b - a)
+                    .cross(&(c - a))
+                    .try_normalize(0.0)
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let corner_normals: Vec<na::Vector3<f32>> = match mode {
+            NormalMode::Flat => face_normals.iter().flat_map(|&n| [n; 3]).collect(),
+            NormalMode::Smooth { crease_angle } => {
+                let threshold = crease_angle.cos();
+                //corners are grouped by position rather than index, so seams in the texcoords
+                //do not show up as seams in the shading
+                let mut corners_at: HashMap<[u32; 3], Vec<(usize, usize)>> = HashMap::new();
+                for (t, triangle) in triangles.iter().enumerate() {
+                    for (k, &vertex) in triangle.iter().enumerate() {
+                        corners_at
+                            .entry(bits(self.positions[vertex]))
+                            .or_default()
+                            .push((t, k));
+                    }
+                }
+                let mut normals = vec![];
+                for (t, triangle) in triangles.iter().enumerate() {
+                    for &vertex in triangle {
+                        let mut sum = na::Vector3::zeros();
+                        for &(other, k) in &corners_at[&bits(self.positions[vertex])] {
+                            if face_normals[t].dot(&face_normals[other]) >= threshold {
+                                sum += face_normals[other] * self.corner_angle(triangles[other], k);
+                            }
+                        }
+                        normals.push(sum.try_normalize(0.0).unwrap_or(face_normals[t]));
+                    }
+                }
+                normals
+            }
+        };
+
+        //one vertex per distinct position, normal and texcoord
+        let has_texcoords = self.texcoords.len() == self.positions.len();
+        let mut mesh = Mesh::default();
+        let mut vertex_of: HashMap<VertexKey, u32> = HashMap::new();
+        for (corner, &vertex) in triangles.iter().flatten().enumerate() {
+            let position = self.positions[vertex];
+            let normal: [f32; 3] = corner_normals[corner].into();
+            let texcoord = if has_texcoords {
+                self.texcoords[vertex]
+            } else {
+                [0.0; 2]
+            };
+            let index = *vertex_of
+                .entry((bits(position), bits(normal), bits(texcoord)))
+                .or_insert_with(|| {
+                    mesh.positions.push(position);
+                    mesh.normals.push(normal);
+                    if has_texcoords {
+                        mesh.texcoords.push(texcoord);
+                    }
+                    (mesh.positions.len() - 1) as u32
+                });
+            mesh.indices.push(index);
+        }
+        mesh.generate_tangents();
+        mesh
+    }
+
+    fn corner_angle(&self, triangle: [usize; 3], corner: usize) -> f32 {
+        let p = vector(self.positions[triangle[corner]]);
+        let a = vector(self.positions[triangle[(corner + 1) % 3]]) - p;
+        let b = vector(self.positions[triangle[(corner + 2) % 3]]) - p;
+        a.angle(&b)
+    }
+
+    //needs normals and texcoords, otherwise the tangents are left empty
+    pub(crate) fn generate_tangents(&mut self) {
+        self.tangents.clear();
+        let amount = self.positions.len();
+        if self.normals.len() != amount || self.texcoords.len() != amount {
+            return;
+        }
+        let mut tangents = vec![na::Vector3::zeros(); amount];
+        let mut bitangents = vec![na::Vector3::zeros(); amount];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ];
+            let edge1 = vector(self.positions[b]) - vector(self.positions[a]);
+            let edge2 = vector(self.positions[c]) - vector(self.positions[a]);
+            let [u1, v1] = [
+                self.texcoords[b][0] - self.texcoords[a][0],
+                self.texcoords[b][1] - self.texcoords[a][1],
+            ];
+            let [u2, v2] = [
+                self.texcoords[c][0] - self.texcoords[a][0],
+                self.texcoords[c][1] - self.texcoords[a][1],
+            ];
+            let determinant = u1 * v2 - u2 * v1;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * v2 - edge2 * v1) / determinant;
+            let bitangent = (edge2 * u1 - edge1 * u2) / determinant;
+            for vertex in [a, b, c] {
+                tangents[vertex] += tangent;
+                bitangents[vertex] += bitangent;
+            }
+        }
+        self.tangents = (0..amount)
+            .map(|vertex| {
+                let normal = vector(self.normals[vertex]);
+                //Gram-Schmidt, falling back to any perpendicular where the texcoords are degenerate
+                let tangent = (tangents[vertex] - normal * normal.dot(&tangents[vertex]))
+                    .try_normalize(1e-12)
+                    .unwrap_or_else(|| {
+                        let axis = if normal.x.abs() < 0.9 {
+                            na::Vector3::x()
+                        } else {
+                            na::Vector3::y()
+                        };
+                        normal.cross(&axis).try_normalize(0.0).unwrap_or_default()
+                    });
+                let handedness = if normal.cross(&tangent).dot(&bitangents[vertex]) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                [tangent.x, tangent.y, tangent.z, handedness]
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //two triangles folded along the x axis by `angle`
+    fn hinge(angle: f32) -> Mesh {
+        Mesh {
+            positions: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, -angle.cos(), angle.sin()],
+            ],
+            indices: vec![0, 1, 2, 0, 3, 1],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flat_normals_split_every_face() {
+        let mesh = hinge(30f32.to_radians()).with_generated_normals(NormalMode::Flat);
+        assert_eq!(mesh.positions.len(), 6);
+        assert!(mesh.normals[0..3].iter().all(|&n| n == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn smooth_normals_respect_the_crease_angle() {
+        let mode = NormalMode::Smooth {
+            crease_angle: 45f32.to_radians(),
+        };
+        //a gentle fold shares the vertices on the hinge
+        let gentle = hinge(20f32.to_radians()).with_generated_normals(mode);
+        assert_eq!(gentle.positions.len(), 4);
+        //a sharp one keeps them apart
+        let sharp = hinge(80f32.to_radians()).with_generated_normals(mode);
+        assert_eq!(sharp.positions.len(), 6);
+        assert!(sharp
+            .normals
+            .iter()
+            .all(|n| (vector(*n).norm() - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn tangents_follow_the_u_direction() {
+        let mut mesh = hinge(0.0);
+        mesh.texcoords = vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, -1.0]];
+        let mesh = mesh.with_generated_normals(NormalMode::Flat);
+        assert_eq!(mesh.tangents.len(), mesh.positions.len());
+        for tangent in &mesh.tangents {
+            assert!((tangent[0] - 1.0).abs() < 1e-5, "{:?}", tangent);
+            assert_eq!(tangent[3], 1.0);
+        }
+    }
+}
@@ -1,195 +1,340 @@
-use crate::buffer::Buffer;
+use crate::buffer::{grown_capacity, Buffer, BufferError, TypedBuffer};
+use crate::culling::{Bounds, Frustum, InstanceTransform, VertexPosition};
+use crate::gltfloader::{self, GltfError};
+use crate::gpuculling::{DrawCommand, GpuInstances};
+use crate::mesh::{Mesh, NormalMode};
+use crate::objloader::{self, ObjError, Skipped};
+use crate::primitives;
+use crate::rendering::PipelineState;
+use crate::slotmap::{merge_ranges, DenseSlotMap, InstanceHandle, InvalidHandle};
+use crate::upload::Uploader;
+use crate::vertexlayout::{BufferLayout, VertexLayout};
 use ash::vk;
-use obj::Position;
+use bytemuck::Pod;
+use nalgebra as na;
+use std::ops::Range;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, VertexLayout)]
 #[repr(C)]
 pub(crate) struct InstanceData {
     pub(crate) modelmatrix: [[f32; 4]; 4],
     pub(crate) colour: [f32; 3],
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, VertexLayout)]
 #[repr(C)]
-pub struct VertexData {
-    pub position: [f32; 3],
-    pub normal: [f32; 3],
+pub(crate) struct VertexData {
+    pub(crate) position: [f32; 3],
+    //all zeros leaves the surface unlit
+    pub(crate) normal: [f32; 3],
 }
 
-#[derive(Debug, Clone)]
-struct InvalidHandle;
-impl std::fmt::Display for InvalidHandle {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "invalid handle")
+//for meshes coloured per vertex, drawn unlit by the shaders in shaders/vertexcolour
+#[derive(Copy, Clone, Debug, PartialEq, VertexLayout)]
+#[repr(C)]
+pub(crate) struct ColouredVertex {
+    pub(crate) position: [f32; 3],
+    pub(crate) colour: [f32; 3],
+}
+
+#[derive(Copy, Clone, Debug, VertexLayout)]
+#[repr(C)]
+pub(crate) struct Transform {
+    pub(crate) modelmatrix: [[f32; 4]; 4],
+}
+
+//repr(C) with nothing but f32 fields, so none of them has padding
+unsafe impl bytemuck::Zeroable for InstanceData {}
+unsafe impl bytemuck::Pod for InstanceData {}
+unsafe impl bytemuck::Zeroable for VertexData {}
+unsafe impl bytemuck::Pod for VertexData {}
+unsafe impl bytemuck::Zeroable for ColouredVertex {}
+unsafe impl bytemuck::Pod for ColouredVertex {}
+unsafe impl bytemuck::Zeroable for Transform {}
+unsafe impl bytemuck::Pod for Transform {}
+
+impl VertexPosition for VertexData {
+    fn position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+impl VertexPosition for ColouredVertex {
+    fn position(&self) -> [f32; 3] {
+        self.position
     }
 }
-impl std::error::Error for InvalidHandle {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
+impl InstanceTransform for InstanceData {
+    fn modelmatrix(&self) -> na::Matrix4<f32> {
+        self.modelmatrix.into()
+    }
+}
+impl InstanceTransform for Transform {
+    fn modelmatrix(&self) -> na::Matrix4<f32> {
+        self.modelmatrix.into()
     }
 }
 
-pub(crate) struct Model<V, I> {
-    vertexdata: Vec<V>,
-    handle_to_index: std::collections::HashMap<usize, usize>,
-    handles: Vec<usize>,
-    instances: Vec<I>,
-    first_invisible: usize,
-    next_handle: usize,
-    vertexbuffer: Option<Buffer>,
-    instancebuffer: Option<Buffer>,
+//edges sharper than this stay hard when loaded meshes get their normals generated
+const CREASE_ANGLE: f32 = std::f32::consts::FRAC_PI_3;
+
+//generates smooth normals if the mesh comes without them
+fn lit(mesh: Mesh) -> Mesh {
+    if mesh.normals.len() == mesh.positions.len() {
+        mesh
+    } else {
+        mesh.with_generated_normals(NormalMode::Smooth {
+            crease_angle: CREASE_ANGLE,
+        })
+    }
 }
-impl<V, I> Model<V, I> {
-    fn get(&self, handle: usize) -> Option<&I> {
-        if let Some(&index) = self.handle_to_index.get(&handle) {
-            self.instances.get(index)
+
+fn vertices(mesh: &Mesh) -> Vec<VertexData> {
+    mesh.positions
+        .iter()
+        .zip(&mesh.normals)
+        .map(|(&position, &normal)| VertexData { position, normal })
+        .collect()
+}
+
+//u16 whenever every vertex can be addressed with one, halving the size of the index buffer
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+impl Indices {
+    pub(crate) fn new(indices: Vec<u32>, amount_of_vertices: usize) -> Indices {
+        if amount_of_vertices <= u16::MAX as usize + 1 {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
         } else {
-            None
+            Indices::U32(indices)
         }
     }
-    fn get_mut(&mut self, handle: usize) -> Option<&mut I> {
-        if let Some(&index) = self.handle_to_index.get(&handle) {
-            self.instances.get_mut(index)
-        } else {
-            None
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
         }
     }
-    fn is_visible(&self, handle: usize) -> Result<bool, InvalidHandle> {
-        if let Some(index) = self.handle_to_index.get(&handle) {
-            Ok(index < &self.first_invisible)
-        } else {
-            Err(InvalidHandle)
+    pub(crate) fn index_type(&self) -> vk::IndexType {
+        match self {
+            Indices::U16(_) => vk::IndexType::UINT16,
+            Indices::U32(_) => vk::IndexType::UINT32,
         }
     }
-    fn make_visible(&mut self, handle: usize) -> Result<(), InvalidHandle> {
-        //if already visible: do nothing
-        if let Some(&index) = self.handle_to_index.get(&handle) {
-            if index < self.first_invisible {
-                return Ok(());
-            }
-            //else: move to position first_invisible and increase value of first_invisible
-            self.swap_by_index(index, self.first_invisible);
-            self.first_invisible += 1;
-            Ok(())
-        } else {
-            Err(InvalidHandle)
+}
+
+//merges bitwise identical vertices and rewrites `indices` to match
+pub(crate) fn deduplicate<V: Pod>(vertices: &[V], indices: &[u32]) -> (Vec<V>, Vec<u32>) {
+    let mut unique = vec![];
+    let mut index_of: std::collections::HashMap<&[u8], u32> = std::collections::HashMap::new();
+    let remapped = indices
+        .iter()
+        .map(|&i| {
+            let vertex = &vertices[i as usize];
+            *index_of
+                .entry(bytemuck::bytes_of(vertex))
+                .or_insert_with(|| {
+                    unique.push(*vertex);
+                    (unique.len() - 1) as u32
+                })
+        })
+        .collect();
+    (unique, remapped)
+}
+
+//the instances one frame draws; the GPU may still be reading the copies of the other frames in
+//flight, so each copy is only written while its own frame is recorded
+struct FrameInstances<I> {
+    buffer: Option<TypedBuffer<I>>,
+    //positions changed since this copy was last written, possibly beyond the visible ones
+    dirty: Vec<Range<usize>>,
+    //how many instances the buffer holds for drawing, fewer than the visible ones after culling
+    drawn: usize,
+    //the frustum and number of visible instances the buffer was culled for; None while it holds
+    //the visible instances slot for slot
+    culled_for: Option<(Frustum, usize)>,
+}
+impl<I> Default for FrameInstances<I> {
+    fn default() -> Self {
+        FrameInstances {
+            buffer: None,
+            dirty: Vec::new(),
+            drawn: 0,
+            culled_for: None,
         }
     }
-    fn make_invisible(&mut self, handle: usize) -> Result<(), InvalidHandle> {
-        //if already invisible: do nothing
-        if let Some(&index) = self.handle_to_index.get(&handle) {
-            if index >= self.first_invisible {
-                return Ok(());
-            }
-            //else: move to position before first_invisible and decrease value of first_invisible
-            self.swap_by_index(index, self.first_invisible - 1);
-            self.first_invisible -= 1;
-            Ok(())
-        } else {
-            Err(InvalidHandle)
+}
+
+pub(crate) struct Model<V, I> {
+    vertexdata: Vec<V>,
+    //None draws vertexdata as a plain triangle list
+    indexdata: Option<Indices>,
+    instances: DenseSlotMap<I>,
+    vertexbuffer: Option<Buffer>,
+    indexbuffer: Option<Buffer>,
+    //one copy per frame in flight, indexed like VkInterface::frames
+    frame_instances: Vec<FrameInstances<I>>,
+    //replaced by bigger or smaller ones, but possibly still in use by frames in flight
+    retired_buffers: Vec<Buffer>,
+    pipeline_state: PipelineState,
+    //of vertexdata, computed when first needed; None without vertices
+    bounds: std::cell::OnceCell<Option<Bounds>>,
+    //the instances the last culled upload kept, reused to not allocate every frame
+    in_view: Vec<I>,
+    //instead of the instance buffer when the instances are culled by a compute shader
+    gpu_instances: Option<GpuInstances>,
+}
+impl<V: Pod, I> Model<V, I> {
+    pub(crate) fn indexed(vertexdata: &[V], indices: &[u32]) -> Model<V, I> {
+        let (vertexdata, indices) = deduplicate(vertexdata, indices);
+        let amount_of_vertices = vertexdata.len();
+        Model {
+            vertexdata,
+            indexdata: Some(Indices::new(indices, amount_of_vertices)),
+            instances: DenseSlotMap::default(),
+            vertexbuffer: None,
+            indexbuffer: None,
+            frame_instances: Vec::new(),
+            retired_buffers: Vec::new(),
+            pipeline_state: PipelineState::default(),
+            bounds: std::cell::OnceCell::new(),
+            in_view: Vec::new(),
+            gpu_instances: None,
         }
     }
-    fn insert(&mut self, element: I) -> usize {
-        let handle = self.next_handle;
-        self.next_handle += 1;
-        let index = self.instances.len();
-        self.instances.push(element);
-        self.handles.push(handle);
-        self.handle_to_index.insert(handle, index);
-        handle
+}
+impl<V, I> Model<V, I> {
+    pub(crate) fn get(&self, handle: InstanceHandle) -> Option<&I> {
+        self.instances.get(handle)
+    }
+    pub(crate) fn get_mut(&mut self, handle: InstanceHandle) -> Option<&mut I> {
+        self.instances.get_mut(handle)
+    }
+    pub(crate) fn is_visible(&self, handle: InstanceHandle) -> Result<bool, InvalidHandle> {
+        self.instances.is_visible(handle)
+    }
+    pub(crate) fn make_visible(&mut self, handle: InstanceHandle) -> Result<(), InvalidHandle> {
+        self.instances.make_visible(handle)
     }
-    pub(crate) fn insert_visibly(&mut self, element: I) -> usize {
+    pub(crate) fn make_invisible(&mut self, handle: InstanceHandle) -> Result<(), InvalidHandle> {
+        self.instances.make_invisible(handle)
+    }
+    pub(crate) fn insert(&mut self, element: I) -> InstanceHandle {
+        self.instances.insert(element)
+    }
+    pub(crate) fn insert_visibly(&mut self, element: I) -> InstanceHandle {
         let new_handle = self.insert(element);
+        //the handle was just created, so it is valid
         self.make_visible(new_handle).ok();
         new_handle
     }
-    fn remove(&mut self, handle: usize) -> Result<I, InvalidHandle> {
-        if let Some(&index) = self.handle_to_index.get(&handle) {
-            if index < self.first_invisible {
-                self.swap_by_index(index, self.first_invisible - 1);
-                self.first_invisible -= 1;
-            }
-            self.swap_by_index(self.first_invisible, self.instances.len() - 1);
-            self.handles.pop();
-            self.handle_to_index.remove(&handle);
-            //must be Some(), otherwise we couldn't have found an index
-            Ok(self.instances.pop().unwrap())
-        } else {
-            Err(InvalidHandle)
-        }
+    pub(crate) fn remove(&mut self, handle: InstanceHandle) -> Result<I, InvalidHandle> {
+        self.instances.remove(handle)
     }
-    fn swap_by_handle(&mut self, handle1: usize, handle2: usize) -> Result<(), InvalidHandle> {
-        if handle1 == handle2 {
-            return Ok(());
-        }
-        if let (Some(&index1), Some(&index2)) = (
-            self.handle_to_index.get(&handle1),
-            self.handle_to_index.get(&handle2),
-        ) {
-            self.handles.swap(index1, index2);
-            self.instances.swap(index1, index2);
-            self.handle_to_index.insert(index1, handle2);
-            self.handle_to_index.insert(index2, handle1);
-            Ok(())
-        } else {
-            Err(InvalidHandle)
-        }
+    pub(crate) fn pipeline_state(&self) -> &PipelineState {
+        &self.pipeline_state
     }
-    fn swap_by_index(&mut self, index1: usize, index2: usize) {
-        if index1 == index2 {
-            return;
-        }
-        let handle1 = self.handles[index1];
-        let handle2 = self.handles[index2];
-        self.handles.swap(index1, index2);
-        self.instances.swap(index1, index2);
-        self.handle_to_index.insert(index1, handle2);
-        self.handle_to_index.insert(index2, handle1);
+    //the pipeline is built the next time VkInterface::prepare_pipelines sees the model
+    pub(crate) fn set_pipeline_state(&mut self, state: PipelineState) {
+        self.pipeline_state = state;
     }
-    pub(crate) fn update_vertexbuffer(
-        &mut self,
-        allocator: &vk_mem::Allocator,
-    ) -> Result<(), vk::Result> {
-        if let Some(buffer) = &mut self.vertexbuffer {
-            unsafe { buffer.fill(allocator, &self.vertexdata)? };
-            Ok(())
-        } else {
-            let bytes = (self.vertexdata.len() * std::mem::size_of::<V>()) as u64;
-            let mut buffer = Buffer::new(
-                &allocator,
-                bytes,
-                vk::BufferUsageFlags::VERTEX_BUFFER,
-                vk_mem::MemoryUsage::CpuToGpu,
-            )?;
-            unsafe { buffer.fill(allocator, &self.vertexdata)? };
-            self.vertexbuffer = Some(buffer);
-            Ok(())
+    //takes over the mesh of `other` while keeping this model's instances and their handles; the old
+    //buffers are retired, frames in flight may still be drawing from them
+    pub(crate) fn replace_mesh<J>(&mut self, other: Model<V, J>) {
+        self.vertexdata = other.vertexdata;
+        self.indexdata = other.indexdata;
+        self.bounds = std::cell::OnceCell::new();
+        self.retire_buffers();
+        self.retired_buffers.extend(
+            [other.vertexbuffer, other.indexbuffer]
+                .into_iter()
+                .flatten()
+                .chain(
+                    other
+                        .frame_instances
+                        .into_iter()
+                        .filter_map(|copy| copy.buffer.map(Buffer::from)),
+                )
+                .chain(
+                    other
+                        .gpu_instances
+                        .into_iter()
+                        .flat_map(GpuInstances::into_buffers),
+                )
+                .chain(other.retired_buffers),
+        );
+    }
+    //for models that are about to be dropped or need fresh buffers
+    pub(crate) fn retire_buffers(&mut self) {
+        for buffer in [&mut self.vertexbuffer, &mut self.indexbuffer] {
+            self.retired_buffers.extend(buffer.take());
         }
+        self.retired_buffers.extend(
+            self.frame_instances
+                .drain(..)
+                .filter_map(|copy| copy.buffer.map(Buffer::from)),
+        );
+        self.retire_gpu_instances();
     }
-    pub(crate) fn update_instancebuffer(
-        &mut self,
-        allocator: &vk_mem::Allocator,
-    ) -> Result<(), vk::Result> {
-        if let Some(buffer) = &mut self.instancebuffer {
-            unsafe { buffer.fill(allocator, &self.instances[0..self.first_invisible])? };
-            Ok(())
-        } else {
-            let bytes = (self.first_invisible * std::mem::size_of::<I>()) as u64;
-            let mut buffer = Buffer::new(
-                &allocator,
-                bytes,
-                vk::BufferUsageFlags::VERTEX_BUFFER,
-                vk_mem::MemoryUsage::CpuToGpu,
-            )?;
-            unsafe { buffer.fill(allocator, &self.instances[0..self.first_invisible])? };
-            self.instancebuffer = Some(buffer);
-            Ok(())
+    fn retire_gpu_instances(&mut self) {
+        self.retired_buffers.extend(
+            self.gpu_instances
+                .take()
+                .into_iter()
+                .flat_map(GpuInstances::into_buffers),
+        );
+    }
+    //hands over replaced buffers, to be destroyed once no frame in flight can still use them
+    pub(crate) fn drain_retired_buffers(&mut self) -> std::vec::Drain<'_, Buffer> {
+        self.retired_buffers.drain(..)
+    }
+    //hands the changes since the last update to the copy of every frame, making sure `frame` has one
+    fn distribute_dirty_ranges(&mut self, frame: usize) {
+        if self.frame_instances.len() <= frame {
+            self.frame_instances
+                .resize_with(frame + 1, FrameInstances::default);
+        }
+        let dirty = self.instances.take_dirty_ranges();
+        for copy in &mut self.frame_instances {
+            copy.dirty.extend(dirty.iter().cloned());
+            merge_ranges(&mut copy.dirty);
         }
     }
-    pub(crate) fn draw(&self, logical_device: &ash::Device, commandbuffer: vk::CommandBuffer) {
+    pub(crate) fn draw(
+        &self,
+        logical_device: &ash::Device,
+        commandbuffer: vk::CommandBuffer,
+        frame: usize,
+    ) {
         if let Some(vertexbuffer) = &self.vertexbuffer {
-            if let Some(instancebuffer) = &self.instancebuffer {
-                if self.first_invisible > 0 {
+            if let Some(gpu_instances) = &self.gpu_instances {
+                unsafe {
+                    logical_device.cmd_bind_vertex_buffers(
+                        commandbuffer,
+                        0,
+                        &[vertexbuffer.buffer],
+                        &[0],
+                    );
+                    if let (Some(indexdata), Some(indexbuffer)) =
+                        (&self.indexdata, &self.indexbuffer)
+                    {
+                        logical_device.cmd_bind_index_buffer(
+                            commandbuffer,
+                            indexbuffer.buffer,
+                            0,
+                            indexdata.index_type(),
+                        );
+                    }
+                    gpu_instances.record_draw(logical_device, commandbuffer);
+                }
+                return;
+            }
+            let Some(copy) = self.frame_instances.get(frame) else {
+                return;
+            };
+            if let Some(instancebuffer) = &copy.buffer {
+                if copy.drawn > 0 {
                     unsafe {
                         logical_device.cmd_bind_vertex_buffers(
                             commandbuffer,
@@ -200,24 +345,305 @@ impl<V, I> Model<V, I> {
                         logical_device.cmd_bind_vertex_buffers(
                             commandbuffer,
                             1,
-                            &[instancebuffer.buffer],
+                            &[instancebuffer.buffer()],
                             &[0],
                         );
-                        logical_device.cmd_draw(
-                            commandbuffer,
-                            self.vertexdata.len() as u32,
-                            self.first_invisible as u32,
-                            0,
-                            0,
-                        );
+                        match (&self.indexdata, &self.indexbuffer) {
+                            (Some(indexdata), Some(indexbuffer)) => {
+                                logical_device.cmd_bind_index_buffer(
+                                    commandbuffer,
+                                    indexbuffer.buffer,
+                                    0,
+                                    indexdata.index_type(),
+                                );
+                                logical_device.cmd_draw_indexed(
+                                    commandbuffer,
+                                    indexdata.len() as u32,
+                                    copy.drawn as u32,
+                                    0,
+                                    0,
+                                    0,
+                                );
+                            }
+                            _ => logical_device.cmd_draw(
+                                commandbuffer,
+                                self.vertexdata.len() as u32,
+                                copy.drawn as u32,
+                                0,
+                                0,
+                            ),
+                        }
                     }
                 }
             }
         }
     }
 }
-impl Model<[f32; 3], InstanceData> {
-    pub(crate) fn cube() -> Model<[f32; 3], InstanceData> {
+impl<V: Pod, I: Pod> Model<V, I> {
+    //uploads the index buffer as well, the two only change together; both go to device-local memory
+    //through the transfer queue, so they are ready once the uploader's next submission has finished
+    pub(crate) fn update_vertexbuffer(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        uploader: &mut Uploader,
+    ) -> Result<(), BufferError> {
+        upload_static(
+            allocator,
+            uploader,
+            &mut self.vertexbuffer,
+            &mut self.retired_buffers,
+            &self.vertexdata,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        )?;
+        match &self.indexdata {
+            Some(Indices::U16(indices)) => upload_static(
+                allocator,
+                uploader,
+                &mut self.indexbuffer,
+                &mut self.retired_buffers,
+                indices,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            ),
+            Some(Indices::U32(indices)) => upload_static(
+                allocator,
+                uploader,
+                &mut self.indexbuffer,
+                &mut self.retired_buffers,
+                indices,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            ),
+            None => Ok(()),
+        }
+    }
+    //brings the copy of `frame` up to date, writing only the visible instances changed since it was
+    //last written unless the buffer has to grow
+    pub(crate) fn update_instancebuffer(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        frame: usize,
+    ) -> Result<(), BufferError> {
+        self.distribute_dirty_ranges(frame);
+        let visible = self.instances.visible();
+        let copy = &mut self.frame_instances[frame];
+        //a culled copy no longer matches the visible instances slot for slot
+        if copy.culled_for.take().is_some() {
+            copy.dirty.clear();
+            copy.dirty.push(0..visible.len());
+        }
+        let result = match &mut copy.buffer {
+            Some(buffer) if buffer.capacity() >= visible.len() => {
+                copy.dirty.iter().try_for_each(|range| {
+                    let range = range.start.min(visible.len())..range.end.min(visible.len());
+                    buffer.write(allocator, range.start, &visible[range])
+                })
+            }
+            _ => upload(
+                allocator,
+                &mut copy.buffer,
+                &mut self.retired_buffers,
+                visible,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ),
+        };
+        copy.dirty.clear();
+        match result {
+            Ok(()) => copy.drawn = visible.len(),
+            Err(_) => copy.dirty.push(0..visible.len()),
+        }
+        result
+    }
+    //gives back memory after many instances were removed or hidden; vertex and index buffers
+    //always fit exactly. Frames in flight keep drawing from the replaced buffers until they finish
+    pub(crate) fn shrink_buffers(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+    ) -> Result<(), BufferError> {
+        let visible = self.instances.visible();
+        for copy in &mut self.frame_instances {
+            if shrink(
+                allocator,
+                &mut copy.buffer,
+                &mut self.retired_buffers,
+                visible,
+            )? {
+                copy.dirty.clear();
+                copy.drawn = visible.len();
+                copy.culled_for = None;
+            }
+        }
+        //the next update_gpu_instances allocates them again, just big enough
+        let capacity = self
+            .gpu_instances
+            .as_ref()
+            .map_or(0, GpuInstances::capacity);
+        if shrunk_capacity(capacity, visible.len()).is_some() {
+            self.retire_gpu_instances();
+        }
+        Ok(())
+    }
+}
+impl<V: Pod + VertexPosition, I: Pod + InstanceTransform> Model<V, I> {
+    pub(crate) fn bounds(&self) -> Option<Bounds> {
+        *self
+            .bounds
+            .get_or_init(|| Bounds::of(self.vertexdata.iter().map(V::position)))
+    }
+    //like update_instancebuffer, but only with the visible instances `frustum` may see; returns
+    //how many were left out. The copy of `frame` is only culled again when the frustum or any
+    //instance has changed since it was last written
+    pub(crate) fn update_instancebuffer_culled(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        frame: usize,
+        frustum: &Frustum,
+    ) -> Result<usize, BufferError> {
+        let Some(bounds) = self.bounds() else {
+            return self.update_instancebuffer(allocator, frame).map(|()| 0);
+        };
+        self.distribute_dirty_ranges(frame);
+        let amount_visible = self.instances.amount_visible();
+        let copy = &mut self.frame_instances[frame];
+        let culled_for = Some((*frustum, amount_visible));
+        if copy.dirty.is_empty() && copy.culled_for == culled_for {
+            return Ok(amount_visible - copy.drawn);
+        }
+        let mut in_view = std::mem::take(&mut self.in_view);
+        in_view.clear();
+        in_view.extend(
+            self.instances
+                .visible()
+                .iter()
+                .filter(|instance| frustum.may_see(&bounds, &instance.modelmatrix())),
+        );
+        let result = upload(
+            allocator,
+            &mut copy.buffer,
+            &mut self.retired_buffers,
+            &in_view,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        copy.dirty.clear();
+        match result {
+            Ok(()) => {
+                copy.drawn = in_view.len();
+                copy.culled_for = culled_for;
+            }
+            Err(_) => {
+                copy.culled_for = None;
+                copy.dirty.push(0..amount_visible);
+            }
+        }
+        let culled = amount_visible - in_view.len();
+        self.in_view = in_view;
+        result.map(|()| culled)
+    }
+}
+impl<V: VertexLayout, I: VertexLayout> Model<V, I> {
+    //binding 0 advances per vertex, binding 1 per instance
+    pub(crate) fn buffer_layouts() -> [BufferLayout; 2] {
+        [BufferLayout::of::<V>(), BufferLayout::of::<I>()]
+    }
+}
+//a model without instances, and the instances a file gives it
+type PlacedModel = (Model<VertexData, InstanceData>, Vec<InstanceData>);
+//the models of an OBJ file, and the statements they were built without
+type ObjModels = (Vec<Model<VertexData, InstanceData>>, Vec<Skipped>);
+
+impl Model<VertexData, InstanceData> {
+    //instead of update_instancebuffer_culled, leaving the culling to a compute shader; only the
+    //visible instances changed since the last frame are copied over, and the buffers are only
+    //replaced when they have to grow
+    pub(crate) fn update_gpu_instances(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        frustum: &Frustum,
+    ) -> Result<(), BufferError> {
+        let mut dirty = self.instances.take_dirty_ranges();
+        let bounds = self.bounds();
+        let draw = match &self.indexdata {
+            Some(indexdata) => DrawCommand::Indexed {
+                index_count: indexdata.len() as u32,
+            },
+            None => DrawCommand::Plain {
+                vertex_count: self.vertexdata.len() as u32,
+            },
+        };
+        let visible = self.instances.visible();
+        let Some(bounds) = bounds.filter(|_| !visible.is_empty()) else {
+            self.retire_gpu_instances();
+            return Ok(());
+        };
+        let capacity = self
+            .gpu_instances
+            .as_ref()
+            .map_or(0, GpuInstances::capacity);
+        if capacity < visible.len() {
+            let capacity = grown_capacity(capacity as u64, visible.len() as u64) as usize;
+            match GpuInstances::new(allocator, capacity, draw) {
+                Ok(replacement) => {
+                    if let Some(old) = self.gpu_instances.replace(replacement) {
+                        self.retired_buffers.extend(old.into_buffers());
+                    }
+                    dirty.clear();
+                    dirty.push(0..visible.len());
+                }
+                Err(e) => {
+                    self.instances.mark_all_dirty();
+                    return Err(e);
+                }
+            }
+        }
+        let gpu_instances = self.gpu_instances.as_mut().unwrap();
+        match gpu_instances.stage(allocator, visible, &dirty) {
+            Ok(staging) => self.retired_buffers.extend(staging),
+            Err(e) => {
+                self.instances.mark_all_dirty();
+                return Err(e);
+            }
+        }
+        gpu_instances.prepare(visible.len(), &bounds, frustum, draw);
+        Ok(())
+    }
+    pub(crate) fn gpu_instances(&self) -> Option<&GpuInstances> {
+        self.gpu_instances.as_ref()
+    }
+    //the mesh needs normals
+    pub(crate) fn from_mesh(mesh: &Mesh) -> Model<VertexData, InstanceData> {
+        Model::indexed(&vertices(mesh), &mesh.indices)
+    }
+    pub(crate) fn uv_sphere(segments: u32, rings: u32) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::uv_sphere(segments, rings))
+    }
+    pub(crate) fn icosphere(subdivisions: u32) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::icosphere(subdivisions))
+    }
+    pub(crate) fn cylinder(segments: u32) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::cylinder(segments))
+    }
+    pub(crate) fn cone(segments: u32) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::cone(segments))
+    }
+    pub(crate) fn capsule(
+        segments: u32,
+        rings: u32,
+        length: f32,
+    ) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::capsule(segments, rings, length))
+    }
+    pub(crate) fn grid(subdivisions: u32) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::grid(subdivisions))
+    }
+    pub(crate) fn torus(
+        segments: u32,
+        rings: u32,
+        minor_radius: f32,
+    ) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::torus(segments, rings, minor_radius))
+    }
+    pub(crate) fn arrow(segments: u32) -> Model<VertexData, InstanceData> {
+        Model::from_mesh(&primitives::arrow(segments))
+    }
+    pub(crate) fn cube() -> Model<VertexData, InstanceData> {
         let lbf = [-1.0, 1.0, -1.0]; //lbf: left-bottom-front
         let lbb = [-1.0, 1.0, 1.0];
         let ltf = [-1.0, -1.0, -1.0];
@@ -226,37 +652,284 @@ impl Model<[f32; 3], InstanceData> {
         let rbb = [1.0, 1.0, 1.0];
         let rtf = [1.0, -1.0, -1.0];
         let rtb = [1.0, -1.0, 1.0];
-        Model {
-            vertexdata: vec![
-                lbf, lbb, rbb, lbf, rbb, rbf, //bottom
-                ltf, rtb, ltb, ltf, rtf, rtb, //top
-                lbf, rtf, ltf, lbf, rbf, rtf, //front
-                lbb, ltb, rtb, lbb, rtb, rbb, //back
-                lbf, ltf, lbb, lbb, ltf, ltb, //left
-                rbf, rbb, rtf, rbb, rtb, rtf, //right
-            ],
-            handle_to_index: std::collections::HashMap::new(),
-            handles: Vec::new(),
-            instances: Vec::new(),
-            first_invisible: 0,
-            next_handle: 0,
-            vertexbuffer: None,
-            instancebuffer: None,
+        let positions = vec![
+            lbf, lbb, rbb, lbf, rbb, rbf, //bottom
+            ltf, rtb, ltb, ltf, rtf, rtb, //top
+            lbf, rtf, ltf, lbf, rbf, rtf, //front
+            lbb, ltb, rtb, lbb, rtb, rbb, //back
+            lbf, ltf, lbb, lbb, ltf, ltb, //left
+            rbf, rbb, rtf, rbb, rtb, rtf, //right
+        ];
+        let mesh = Mesh {
+            indices: (0..positions.len() as u32).collect(),
+            positions,
+            ..Default::default()
         }
+        .with_generated_normals(NormalMode::Flat);
+        Model::from_mesh(&mesh)
     }
-    pub(crate) fn object(filepath: &str) -> Model<[f32; 3], InstanceData> {
-        let mut reader = std::io::BufReader::new(std::fs::File::open(filepath).expect("404"));
-        let converted: obj::Obj<Position> = obj::load_obj(reader).unwrap();
-        let pos_to_vec: Vec<[f32; 3]> = converted.vertices.iter().map(|p| p.position).collect();
-        Model {
-            vertexdata: pos_to_vec,
-            handle_to_index: std::collections::HashMap::new(),
-            handles: Vec::new(),
-            instances: Vec::new(),
-            first_invisible: 0,
-            next_handle: 0,
-            vertexbuffer: None,
-            instancebuffer: None,
+    //one model per group in the file
+    pub(crate) fn object<P: AsRef<std::path::Path>>(filepath: P) -> Result<ObjModels, ObjError> {
+        let file = objloader::load(filepath)?;
+        let models = file
+            .meshes
+            .into_iter()
+            .map(|mesh| {
+                let mesh = lit(Mesh {
+                    positions: mesh.positions,
+                    normals: mesh.normals,
+                    texcoords: mesh.texcoords,
+                    tangents: vec![],
+                    indices: mesh.indices,
+                });
+                Model::from_mesh(&mesh)
+            })
+            .collect();
+        Ok((models, file.skipped))
+    }
+    //one model per triangle primitive, with an instance for every node that uses it
+    pub(crate) fn gltf<P: AsRef<std::path::Path>>(
+        filepath: P,
+    ) -> Result<Vec<Model<VertexData, InstanceData>>, GltfError> {
+        Ok(Model::gltf_meshes(filepath)?
+            .into_iter()
+            .map(|(mut model, instances)| {
+                for instance in instances {
+                    model.insert_visibly(instance);
+                }
+                model
+            })
+            .collect())
+    }
+    //like `gltf`, but the models come without instances and the ones the nodes describe are
+    //handed back next to them
+    pub(crate) fn gltf_meshes<P: AsRef<std::path::Path>>(
+        filepath: P,
+    ) -> Result<Vec<PlacedModel>, GltfError> {
+        let primitives = gltfloader::load(filepath)?;
+        Ok(primitives
+            .into_iter()
+            .map(|primitive| {
+                let mesh = lit(Mesh {
+                    positions: primitive.positions,
+                    normals: primitive.normals,
+                    texcoords: primitive.texcoords,
+                    tangents: vec![],
+                    indices: primitive.indices,
+                });
+                (Model::from_mesh(&mesh), primitive.instances)
+            })
+            .collect())
+    }
+}
+
+//copies `data` into `buffer`, first growing it if it is too small
+fn upload<T: Pod>(
+    allocator: &vk_mem::Allocator,
+    buffer: &mut Option<TypedBuffer<T>>,
+    retired_buffers: &mut Vec<Buffer>,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+) -> Result<(), BufferError> {
+    match buffer {
+        Some(existing) if existing.capacity() >= data.len() => {}
+        Some(existing) => {
+            let capacity = grown_capacity(existing.capacity() as u64, data.len() as u64);
+            let grown = existing.reallocated(allocator, capacity as usize)?;
+            retired_buffers.push(std::mem::replace(existing, grown).into());
         }
+        //Vulkan does not allow empty buffers, so wait until there is something to upload
+        None if data.is_empty() => return Ok(()),
+        None => {
+            *buffer = Some(TypedBuffer::new(
+                allocator,
+                grown_capacity(0, data.len() as u64) as usize,
+                usage,
+                vk_mem::MemoryUsage::CpuToGpu,
+            )?);
+        }
+    }
+    if let Some(buffer) = buffer {
+        buffer.write(allocator, 0, data)?;
+    }
+    Ok(())
+}
+
+//replaces `buffer` with a device-local copy of `data`; frames in flight may still read the old one
+fn upload_static<T: Pod>(
+    allocator: &vk_mem::Allocator,
+    uploader: &mut Uploader,
+    buffer: &mut Option<Buffer>,
+    retired_buffers: &mut Vec<Buffer>,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+) -> Result<(), BufferError> {
+    retired_buffers.extend(buffer.take());
+    //Vulkan does not allow empty buffers
+    if std::mem::size_of_val(data) > 0 {
+        *buffer = Some(uploader.upload(allocator, data, usage)?);
+    }
+    Ok(())
+}
+
+//what a buffer of `capacity` elements shrinks to when it only has to hold `amount`, if that is
+//any smaller; the new capacity still leaves room to grow like a fresh buffer would
+fn shrunk_capacity(capacity: usize, amount: usize) -> Option<usize> {
+    let shrunk = grown_capacity(0, amount as u64) as usize;
+    (shrunk < capacity).then_some(shrunk)
+}
+
+//reallocates `buffer` to fit `data` more tightly, or drops it if `data` is empty; returns whether
+//it was replaced
+fn shrink<T: Pod>(
+    allocator: &vk_mem::Allocator,
+    buffer: &mut Option<TypedBuffer<T>>,
+    retired_buffers: &mut Vec<Buffer>,
+    data: &[T],
+) -> Result<bool, BufferError> {
+    let Some(existing) = buffer else {
+        return Ok(false);
+    };
+    if data.is_empty() {
+        retired_buffers.extend(buffer.take().map(Buffer::from));
+        return Ok(true);
+    }
+    let Some(capacity) = shrunk_capacity(existing.capacity(), data.len()) else {
+        return Ok(false);
+    };
+    let mut shrunk = existing.reallocated(allocator, capacity)?;
+    if let Err(e) = shrunk.write(allocator, 0, data) {
+        unsafe { shrunk.destroy(allocator) };
+        return Err(e);
+    }
+    retired_buffers.push(std::mem::replace(existing, shrunk).into());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deduplicate_merges_identical_vertices() {
+        let vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let (unique, indices) = deduplicate(&vertices, &[0, 1, 3, 2, 3, 1]);
+        assert_eq!(
+            unique,
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 1]);
+    }
+
+    #[test]
+    fn cube_shares_its_corners() {
+        let cube = Model::cube();
+        //each corner appears once per face it belongs to, because the normals differ
+        assert_eq!(cube.vertexdata.len(), 24);
+        assert_eq!(cube.indexdata.as_ref().map(Indices::len), Some(36));
+    }
+
+    #[test]
+    fn replacing_the_mesh_keeps_the_instances() {
+        let mut model = Model::cube();
+        let handle = model.insert_visibly(InstanceData {
+            modelmatrix: [[0.0; 4]; 4],
+            colour: [0.0, 1.0, 0.0],
+        });
+        let sphere = Model::uv_sphere(8, 4);
+        let amount_of_vertices = sphere.vertexdata.len();
+        model.replace_mesh(sphere);
+        assert_eq!(model.vertexdata.len(), amount_of_vertices);
+        assert_eq!(model.get(handle).unwrap().colour, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn stale_handles_reach_no_instance() {
+        let mut model = Model::cube();
+        let instance = |red| InstanceData {
+            modelmatrix: [[0.0; 4]; 4],
+            colour: [red, 0.0, 0.0],
+        };
+        let first = model.insert_visibly(instance(0.0));
+        let second = model.insert_visibly(instance(1.0));
+        model.make_invisible(first).unwrap();
+        assert_eq!(model.is_visible(first), Ok(false));
+        assert_eq!(model.remove(first).map(|i| i.colour[0]), Ok(0.0));
+        assert!(model.get_mut(first).is_none());
+        assert_eq!(model.make_visible(first), Err(InvalidHandle));
+        //the slot of `first` is reused, but its handle stays stale
+        let third = model.insert(instance(2.0));
+        assert!(model.get(first).is_none());
+        assert_eq!(model.get(third).map(|i| i.colour[0]), Some(2.0));
+        assert_eq!(model.get(second).map(|i| i.colour[0]), Some(1.0));
+    }
+
+    #[test]
+    fn every_frame_catches_up_on_changes() {
+        let mut model = Model::cube();
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                model.insert_visibly(InstanceData {
+                    modelmatrix: [[0.0; 4]; 4],
+                    colour: [0.0; 3],
+                })
+            })
+            .collect();
+        model.distribute_dirty_ranges(1);
+        //frame 0 was written, frame 1 was not
+        model.frame_instances[0].dirty.clear();
+        model.get_mut(handles[1]).unwrap().colour = [1.0; 3];
+        model.distribute_dirty_ranges(0);
+        assert_eq!(model.frame_instances[0].dirty, vec![1..2]);
+        assert_eq!(model.frame_instances[1].dirty, vec![0..3]);
+    }
+
+    #[test]
+    fn only_touched_and_swapped_instances_are_uploaded() {
+        let mut model = Model::cube();
+        let handles: Vec<_> = (0..6)
+            .map(|_| {
+                model.insert_visibly(InstanceData {
+                    modelmatrix: [[0.0; 4]; 4],
+                    colour: [0.0; 3],
+                })
+            })
+            .collect();
+        model.distribute_dirty_ranges(0);
+        assert_eq!(model.frame_instances[0].dirty, vec![0..6]);
+        model.frame_instances[0].dirty.clear();
+        model.get_mut(handles[4]).unwrap().colour = [1.0; 3];
+        //the last visible instance takes the place of the hidden one
+        model.make_invisible(handles[1]).unwrap();
+        model.distribute_dirty_ranges(0);
+        assert_eq!(model.frame_instances[0].dirty, vec![1..2, 4..5]);
+        assert_eq!(model.instances.amount_visible(), 5);
+    }
+
+    #[test]
+    fn buffers_shrink_to_what_a_fresh_one_would_hold() {
+        assert_eq!(shrunk_capacity(1024, 3), Some(4));
+        assert_eq!(shrunk_capacity(1024, 0), Some(1));
+        //already as small as a new buffer for the same amount
+        assert_eq!(shrunk_capacity(8, 5), None);
+        assert_eq!(shrunk_capacity(8, 8), None);
+        assert_eq!(shrunk_capacity(0, 3), None);
+    }
+
+    #[test]
+    fn index_width_follows_the_vertex_count() {
+        assert_eq!(
+            Indices::new(vec![0, 1, 2], 65536),
+            Indices::U16(vec![0, 1, 2])
+        );
+        assert_eq!(
+            Indices::new(vec![0, 1, 2], 65537),
+            Indices::U32(vec![0, 1, 2])
+        );
     }
 }
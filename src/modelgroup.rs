@@ -0,0 +1,111 @@
+//models whose vertex or instance type is not VertexData or InstanceData, drawn with shaders written
+//for their layout; VkInterface keeps the groups without knowing their types
+use crate::buffer::{Buffer, BufferError};
+use crate::model::Model;
+use crate::rendering::PipelineState;
+use crate::upload::Uploader;
+use crate::vertexlayout::{BufferLayout, VertexLayout};
+use bytemuck::Pod;
+use std::path::{Path, PathBuf};
+
+pub(crate) struct ModelGroup<V, I> {
+    pub(crate) models: Vec<Model<V, I>>,
+    //holding shader.vert and shader.frag
+    shader_directory: PathBuf,
+}
+impl<V, I> ModelGroup<V, I> {
+    pub(crate) fn new(shader_directory: PathBuf) -> ModelGroup<V, I> {
+        ModelGroup {
+            models: vec![],
+            shader_directory,
+        }
+    }
+}
+
+//what VkInterface needs of a group; buffers that get replaced are handed to `retired`, and
+//instances are written and drawn for one frame in flight at a time
+pub(crate) trait AnyModelGroup {
+    fn shader_directory(&self) -> &Path;
+    fn buffer_layouts(&self) -> [BufferLayout; 2];
+    fn update_vertexbuffers(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        uploader: &mut Uploader,
+        retired: &mut Vec<Buffer>,
+    ) -> Result<(), BufferError>;
+    fn update_instancebuffers(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        frame: usize,
+        retired: &mut Vec<Buffer>,
+    ) -> Result<(), BufferError>;
+    //hands over all buffers, for groups that are about to be dropped
+    fn retire_buffers(&mut self, retired: &mut Vec<Buffer>);
+    fn len(&self) -> usize;
+    fn pipeline_state(&self, model: usize) -> &PipelineState;
+    fn draw(
+        &self,
+        model: usize,
+        logical_device: &ash::Device,
+        commandbuffer: ash::vk::CommandBuffer,
+        frame: usize,
+    );
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+}
+
+impl<V: VertexLayout + Pod, I: VertexLayout + Pod> AnyModelGroup for ModelGroup<V, I> {
+    fn shader_directory(&self) -> &Path {
+        &self.shader_directory
+    }
+    fn buffer_layouts(&self) -> [BufferLayout; 2] {
+        Model::<V, I>::buffer_layouts()
+    }
+    fn update_vertexbuffers(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        uploader: &mut Uploader,
+        retired: &mut Vec<Buffer>,
+    ) -> Result<(), BufferError> {
+        for m in &mut self.models {
+            m.update_vertexbuffer(allocator, uploader)?;
+            retired.extend(m.drain_retired_buffers());
+        }
+        Ok(())
+    }
+    fn update_instancebuffers(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        frame: usize,
+        retired: &mut Vec<Buffer>,
+    ) -> Result<(), BufferError> {
+        for m in &mut self.models {
+            m.update_instancebuffer(allocator, frame)?;
+            retired.extend(m.drain_retired_buffers());
+        }
+        Ok(())
+    }
+    fn retire_buffers(&mut self, retired: &mut Vec<Buffer>) {
+        for m in &mut self.models {
+            m.retire_buffers();
+            retired.extend(m.drain_retired_buffers());
+        }
+    }
+    fn len(&self) -> usize {
+        self.models.len()
+    }
+    fn pipeline_state(&self, model: usize) -> &PipelineState {
+        self.models[model].pipeline_state()
+    }
+    fn draw(
+        &self,
+        model: usize,
+        logical_device: &ash::Device,
+        commandbuffer: ash::vk::CommandBuffer,
+        frame: usize,
+    ) {
+        self.models[model].draw(logical_device, commandbuffer, frame);
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
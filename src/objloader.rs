@@ -0,0 +1,349 @@
+//Wavefront OBJ import: every `o`/`g` group becomes its own mesh, polygons are fan-triangulated and
+//each distinct position/texcoord/normal combination of a face corner becomes one vertex
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub(crate) enum ObjError {
+    Open {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Read(std::io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ObjError::Open { path, source } => {
+                write!(f, "could not open {}: {}", path.display(), source)
+            }
+            ObjError::Read(e) => write!(f, "could not read OBJ data: {}", e),
+            ObjError::Parse { line, message } => write!(f, "OBJ line {}: {}", line, message),
+        }
+    }
+}
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Open { source, .. } => Some(source),
+            ObjError::Read(e) => Some(e),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+//normals and texcoords are either empty or as long as positions
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ObjMesh {
+    pub(crate) name: String,
+    pub(crate) positions: Vec<[f32; 3]>,
+    pub(crate) normals: Vec<[f32; 3]>,
+    pub(crate) texcoords: Vec<[f32; 2]>,
+    pub(crate) indices: Vec<u32>,
+}
+
+//a kind of statement the loader does not understand and left out, with the line it first
+//appeared on
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Skipped {
+    pub(crate) line: usize,
+    pub(crate) keyword: String,
+}
+impl std::fmt::Display for Skipped {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "OBJ line {}: skipped '{}' statements",
+            self.line, self.keyword
+        )
+    }
+}
+
+//the meshes of a file, and what was left out of them
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ObjFile {
+    pub(crate) meshes: Vec<ObjMesh>,
+    pub(crate) skipped: Vec<Skipped>,
+}
+
+//one face corner, as indices into the file-wide attribute lists
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct Group {
+    mesh: ObjMesh,
+    vertex_of_corner: HashMap<Corner, u32>,
+    corners: Vec<Corner>,
+}
+impl Group {
+    fn new(name: &str) -> Group {
+        Group {
+            mesh: ObjMesh {
+                name: name.to_string(),
+                ..Default::default()
+            },
+            vertex_of_corner: HashMap::new(),
+            corners: vec![],
+        }
+    }
+    fn vertex(&mut self, corner: Corner) -> u32 {
+        let next = self.corners.len() as u32;
+        *self.vertex_of_corner.entry(corner).or_insert_with(|| {
+            self.corners.push(corner);
+            next
+        })
+    }
+    fn finish(
+        self,
+        positions: &[[f32; 3]],
+        texcoords: &[[f32; 2]],
+        normals: &[[f32; 3]],
+    ) -> ObjMesh {
+        let mut mesh = self.mesh;
+        let has_texcoords = self.corners.iter().any(|c| c.1.is_some());
+        let has_normals = self.corners.iter().any(|c| c.2.is_some());
+        for (position, texcoord, normal) in self.corners {
+            mesh.positions.push(positions[position]);
+            //corners without one in an otherwise textured or lit group get zeros
+            if has_texcoords {
+                mesh.texcoords
+                    .push(texcoord.map_or([0.0; 2], |t| texcoords[t]));
+            }
+            if has_normals {
+                mesh.normals.push(normal.map_or([0.0; 3], |n| normals[n]));
+            }
+        }
+        mesh
+    }
+}
+
+pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<ObjFile, ObjError> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path).map_err(|source| ObjError::Open {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(std::io::BufReader::new(file))
+}
+
+pub(crate) fn parse<R: BufRead>(reader: R) -> Result<ObjFile, ObjError> {
+    let mut positions = vec![];
+    let mut texcoords = vec![];
+    let mut normals = vec![];
+    let mut groups = vec![Group::new("default")];
+    let mut skipped: Vec<Skipped> = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(ObjError::Read)?;
+        let number = number + 1;
+        let error = |message: String| ObjError::Parse {
+            line: number,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let arguments: Vec<&str> = words.collect();
+        match keyword {
+            "v" => positions.push(floats::<3>(&arguments, 3).map_err(error)?),
+            "vt" => texcoords.push(floats::<2>(&arguments, 1).map_err(error)?),
+            "vn" => normals.push(floats::<3>(&arguments, 3).map_err(error)?),
+            "o" | "g" => groups.push(Group::new(&arguments.join(" "))),
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!("face with {} corners", arguments.len())));
+                }
+                let group = groups.last_mut().unwrap();
+                let mut face = Vec::with_capacity(arguments.len());
+                for argument in &arguments {
+                    let corner = corner(argument, positions.len(), texcoords.len(), normals.len())
+                        .map_err(error)?;
+                    face.push(group.vertex(corner));
+                }
+                //a fan, which is exact for the convex polygons exporters write
+                for i in 1..face.len() - 1 {
+                    group.mesh.indices.extend([face[0], face[i], face[i + 1]]);
+                }
+            }
+            //materials, smoothing groups, lines and points do not affect the meshes
+            "mtllib" | "usemtl" | "s" | "l" | "p" => {}
+            //neither do free-form geometry, render attributes or anything newer
+            _ => {
+                if !skipped.iter().any(|s| s.keyword == keyword) {
+                    skipped.push(Skipped {
+                        line: number,
+                        keyword: keyword.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    let meshes = groups
+        .into_iter()
+        .filter(|group| !group.mesh.indices.is_empty())
+        .map(|group| group.finish(&positions, &texcoords, &normals))
+        .collect();
+    Ok(ObjFile { meshes, skipped })
+}
+
+//reads at least `required` and at most N numbers, missing ones are 0
+fn floats<const N: usize>(arguments: &[&str], required: usize) -> Result<[f32; N], String> {
+    //positions may carry a fourth weight and texcoords a third coordinate, neither is used
+    if arguments.len() < required {
+        return Err(format!(
+            "expected {} numbers, found {}",
+            required,
+            arguments.len()
+        ));
+    }
+    let mut values = [0.0; N];
+    for (value, argument) in values.iter_mut().zip(arguments) {
+        *value = argument
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", argument))?;
+    }
+    Ok(values)
+}
+
+//parses `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn corner(
+    argument: &str,
+    positions: usize,
+    texcoords: usize,
+    normals: usize,
+) -> Result<Corner, String> {
+    let mut parts = argument.split('/');
+    let position = index(parts.next().unwrap_or(""), positions)?
+        .ok_or_else(|| format!("face corner '{}' has no position", argument))?;
+    let texcoord = index(parts.next().unwrap_or(""), texcoords)?;
+    let normal = index(parts.next().unwrap_or(""), normals)?;
+    if parts.next().is_some() {
+        return Err(format!("malformed face corner '{}'", argument));
+    }
+    Ok((position, texcoord, normal))
+}
+
+//OBJ indices start at 1, negative ones count back from the latest element
+fn index(part: &str, amount: usize) -> Result<Option<usize>, String> {
+    if part.is_empty() {
+        return Ok(None);
+    }
+    let value: i64 = part
+        .parse()
+        .map_err(|_| format!("'{}' is not an index", part))?;
+    let index = if value > 0 {
+        value - 1
+    } else {
+        amount as i64 + value
+    };
+    if value == 0 || index < 0 || index >= amount as i64 {
+        return Err(format!(
+            "index {} out of range, {} defined so far",
+            value, amount
+        ));
+    }
+    Ok(Some(index as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(source: &str) -> Result<Vec<ObjMesh>, ObjError> {
+        parse(source.as_bytes()).map(|file| file.meshes)
+    }
+
+    #[test]
+    fn quads_are_triangulated_and_corners_shared() {
+        let meshes = parse_str("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].positions.len(), 4);
+        assert_eq!(meshes[0].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert!(meshes[0].normals.is_empty());
+        assert!(meshes[0].texcoords.is_empty());
+    }
+
+    #[test]
+    fn corners_with_different_attributes_are_separate_vertices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 1\nvn 0 0 1\nvn 0 0 -1\n\
+                      f 1/1/1 2/2/1 3/1/1\nf 1/1/2 3/1/2 2/2/2\n";
+        let mesh = &parse_str(source).unwrap()[0];
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.normals[0], [0.0, 0.0, 1.0]);
+        assert_eq!(mesh.normals[3], [0.0, 0.0, -1.0]);
+        assert_eq!(mesh.texcoords[1], [1.0, 1.0]);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn groups_become_separate_meshes() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+                      o first\nf 1 2 3\ng empty\ng second\nf -1 -2 -3 # comment\n";
+        let meshes = parse_str(source).unwrap();
+        let names: Vec<&str> = meshes.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+        assert_eq!(
+            meshes[1].positions,
+            vec![[1.0, 1.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn unsupported_statements_are_skipped() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvp 0.5 0.5\ncstype bspline\ndeg 3\n\
+                      curv 0 1 1 2 3\nsurf 0 1 0 1 1 2 3\nend\nbevel off\nlod 1\n\
+                      shadow_obj shadow.obj\nf 1 2 3\nvp 0.2 0.2\n";
+        let file = parse(source.as_bytes()).unwrap();
+        assert_eq!(file.meshes.len(), 1);
+        assert_eq!(file.meshes[0].indices, vec![0, 1, 2]);
+        //once per keyword, at its first line
+        let skipped: Vec<(usize, &str)> = file
+            .skipped
+            .iter()
+            .map(|s| (s.line, s.keyword.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (4, "vp"),
+                (5, "cstype"),
+                (6, "deg"),
+                (7, "curv"),
+                (8, "surf"),
+                (9, "end"),
+                (10, "bevel"),
+                (11, "lod"),
+                (12, "shadow_obj")
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_input_reports_the_line() {
+        let error = parse_str("v 0 0 0\n\nf 1 2 3\n").unwrap_err();
+        assert!(
+            matches!(error, ObjError::Parse { line: 3, .. }),
+            "{}",
+            error
+        );
+        let error = parse_str("v 0 zero 0\n").unwrap_err();
+        assert!(
+            matches!(error, ObjError::Parse { line: 1, .. }),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        assert!(matches!(
+            load("does/not/exist.obj"),
+            Err(ObjError::Open { .. })
+        ));
+    }
+}
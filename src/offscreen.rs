@@ -0,0 +1,229 @@
+use crate::buffer::Buffer;
+use crate::initialization::QueueFamilies;
+use ash::vk;
+use vk_mem::Alloc;
+
+//stands in for the swapchain when there is no window: one colour and depth target plus a
+//host-visible buffer the finished image is copied into
+pub(crate) struct Offscreen {
+    colour_image: vk::Image,
+    colour_image_allocation: vk_mem::Allocation,
+    colour_imageview: vk::ImageView,
+    depth_image: vk::Image,
+    depth_image_allocation: vk_mem::Allocation,
+    depth_imageview: vk::ImageView,
+    pub(crate) framebuffer: vk::Framebuffer,
+    pub(crate) readback: Buffer,
+    pub(crate) extent: vk::Extent2D,
+}
+
+impl Offscreen {
+    pub(crate) const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    pub(crate) fn init(
+        logical_device: &ash::Device,
+        queue_families: &QueueFamilies,
+        allocator: &vk_mem::Allocator,
+        extent: vk::Extent2D,
+    ) -> Result<Offscreen, vk::Result> {
+        let queuefamilies = [queue_families.graphics_q_index.unwrap()];
+        let extent3d = vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+        let allocation_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::GpuOnly,
+            ..Default::default()
+        };
+        let colour_image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(Self::FORMAT)
+            .extent(extent3d)
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .queue_family_indices(&queuefamilies);
+        let (colour_image, colour_image_allocation) =
+            unsafe { allocator.create_image(&colour_image_info, &allocation_info)? };
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let imageview_create_info = vk::ImageViewCreateInfo::builder()
+            .image(colour_image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(Self::FORMAT)
+            .subresource_range(*subresource_range);
+        let colour_imageview =
+            unsafe { logical_device.create_image_view(&imageview_create_info, None) }?;
+
+        let depth_image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(vk::Format::D32_SFLOAT)
+            .extent(extent3d)
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .queue_family_indices(&queuefamilies);
+        let (depth_image, depth_image_allocation) =
+            unsafe { allocator.create_image(&depth_image_info, &allocation_info)? };
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let imageview_create_info = vk::ImageViewCreateInfo::builder()
+            .image(depth_image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(vk::Format::D32_SFLOAT)
+            .subresource_range(*subresource_range);
+        let depth_imageview =
+            unsafe { logical_device.create_image_view(&imageview_create_info, None) }?;
+
+        let readback = Buffer::new(
+            allocator,
+            extent.width as u64 * extent.height as u64 * 4,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk_mem::MemoryUsage::GpuToCpu,
+        )?;
+
+        Ok(Offscreen {
+            colour_image,
+            colour_image_allocation,
+            colour_imageview,
+            depth_image,
+            depth_image_allocation,
+            depth_imageview,
+            framebuffer: vk::Framebuffer::null(),
+            readback,
+            extent,
+        })
+    }
+    pub(crate) fn create_framebuffer(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: vk::RenderPass,
+    ) -> Result<(), vk::Result> {
+        let iview = [self.colour_imageview, self.depth_imageview];
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(renderpass)
+            .attachments(&iview)
+            .width(self.extent.width)
+            .height(self.extent.height)
+            .layers(1);
+        self.framebuffer = unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
+        Ok(())
+    }
+    //expects to be recorded right after the render pass, which leaves the image in TRANSFER_SRC_OPTIMAL
+    pub(crate) unsafe fn record_readback(
+        &self,
+        logical_device: &ash::Device,
+        commandbuffer: vk::CommandBuffer,
+    ) {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build();
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.colour_image)
+            .subresource_range(subresource_range)
+            .build();
+        logical_device.cmd_pipeline_barrier(
+            commandbuffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[image_barrier],
+        );
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            })
+            .build();
+        logical_device.cmd_copy_image_to_buffer(
+            commandbuffer,
+            self.colour_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.readback.buffer,
+            &[region],
+        );
+        let buffer_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.readback.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+        logical_device.cmd_pipeline_barrier(
+            commandbuffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[buffer_barrier],
+            &[],
+        );
+    }
+    pub(crate) unsafe fn cleanup(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &vk_mem::Allocator,
+    ) {
+        self.readback.destroy(allocator);
+        logical_device.destroy_framebuffer(self.framebuffer, None);
+        logical_device.destroy_image_view(self.depth_imageview, None);
+        allocator.destroy_image(self.depth_image, &mut self.depth_image_allocation);
+        logical_device.destroy_image_view(self.colour_imageview, None);
+        allocator.destroy_image(self.colour_image, &mut self.colour_image_allocation);
+    }
+}
+
+//pixels are tightly packed RGBA8 rows, as produced by VkInterface::render_offscreen
+pub(crate) fn write_png<P: AsRef<std::path::Path>>(
+    path: P,
+    extent: vk::Extent2D,
+    pixels: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), extent.width, extent.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//pipelines compiled in earlier runs, kept in a file so drivers that compile slowly only do so once;
+//the file is ignored when it was written for another device or driver
+use ash::vk;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub(crate) enum PipelineCacheError {
+    Vulkan(vk::Result),
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+impl std::fmt::Display for PipelineCacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PipelineCacheError::Vulkan(e) => write!(f, "could not read the pipeline cache: {}", e),
+            PipelineCacheError::Io { path, source } => write!(
+                f,
+                "could not save the pipeline cache to {}: {}",
+                path.display(),
+                source
+            ),
+        }
+    }
+}
+impl std::error::Error for PipelineCacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineCacheError::Vulkan(e) => Some(e),
+            PipelineCacheError::Io { source, .. } => Some(source),
+        }
+    }
+}
+impl From<vk::Result> for PipelineCacheError {
+    fn from(e: vk::Result) -> Self {
+        PipelineCacheError::Vulkan(e)
+    }
+}
+
+//the platform's directory for cache files, with a file of our own in it
+pub(crate) fn default_path() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let directory = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Caches"))
+    } else {
+        //relative paths in XDG_CACHE_HOME are to be ignored
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|directory| directory.is_absolute())
+            .or_else(|| home().map(|home| home.join(".cache")))
+    }?;
+    Some(directory.join("graphics").join("pipelines.bin"))
+}
+
+//the header every driver puts in front of its data: length, version, vendor, device and the UUID
+//that changes with the driver
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn is_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    data.len() >= HEADER_SIZE
+        && read_u32(data, 0) as usize >= HEADER_SIZE
+        && read_u32(data, 4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(data, 8) == properties.vendor_id
+        && read_u32(data, 12) == properties.device_id
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+
+pub(crate) struct PipelineCache {
+    pub(crate) cache: vk::PipelineCache,
+    //None keeps the cache in memory only
+    path: Option<PathBuf>,
+}
+impl PipelineCache {
+    //starts empty if the file is missing, unreadable or from another device
+    pub(crate) fn init(
+        logical_device: &ash::Device,
+        properties: &vk::PhysicalDeviceProperties,
+        path: Option<PathBuf>,
+    ) -> Result<PipelineCache, vk::Result> {
+        let data = path
+            .as_deref()
+            .and_then(|path| std::fs::read(path).ok())
+            .filter(|data| is_compatible(data, properties))
+            .unwrap_or_default();
+        let cache_info = vk::PipelineCacheCreateInfo::builder().initial_data(&data);
+        let cache = unsafe { logical_device.create_pipeline_cache(&cache_info, None)? };
+        Ok(PipelineCache { cache, path })
+    }
+    //writes to a temporary file first, so an interrupted save cannot leave a broken cache behind
+    pub(crate) fn save(&self, logical_device: &ash::Device) -> Result<(), PipelineCacheError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = unsafe { logical_device.get_pipeline_cache_data(self.cache)? };
+        let io_error = |source| PipelineCacheError::Io {
+            path: path.clone(),
+            source,
+        };
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory).map_err(io_error)?;
+        }
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, data).map_err(io_error)?;
+        std::fs::rename(&temporary, path).map_err(io_error)
+    }
+    pub(crate) unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        logical_device.destroy_pipeline_cache(self.cache, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(vendor_id: u32, device_id: u32, uuid: [u8; vk::UUID_SIZE]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&vendor_id.to_le_bytes());
+        data.extend_from_slice(&device_id.to_le_bytes());
+        data.extend_from_slice(&uuid);
+        //what the driver stores after the header
+        data.extend_from_slice(&[7; 64]);
+        data
+    }
+
+    #[test]
+    fn only_caches_of_the_same_device_and_driver_are_used() {
+        let properties = vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2204,
+            pipeline_cache_uuid: [3; vk::UUID_SIZE],
+            ..Default::default()
+        };
+        assert!(is_compatible(&header(0x10de, 0x2204, [3; 16]), &properties));
+        assert!(!is_compatible(
+            &header(0x1002, 0x2204, [3; 16]),
+            &properties
+        ));
+        assert!(!is_compatible(
+            &header(0x10de, 0x1234, [3; 16]),
+            &properties
+        ));
+        //a driver update
+        assert!(!is_compatible(
+            &header(0x10de, 0x2204, [4; 16]),
+            &properties
+        ));
+        assert!(!is_compatible(
+            &header(0x10de, 0x2204, [3; 16])[..20],
+            &properties
+        ));
+        assert!(!is_compatible(&[], &properties));
+    }
+}
@@ -0,0 +1,390 @@
+//procedural shapes, all centred on the origin with y as their axis and a size of about 2, so they
+//line up with Model::cube; every triangle is wound counter-clockwise seen from outside, like the cube
+use crate::mesh::Mesh;
+use nalgebra as na;
+use std::f32::consts::{PI, TAU};
+
+//a point of the outline that gets rotated around the y axis
+#[derive(Copy, Clone)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    //normal in the plane of the outline: outwards and along y
+    normal: [f32; 2],
+    v: f32,
+}
+
+fn point(radius: f32, y: f32, normal: [f32; 2], v: f32) -> ProfilePoint {
+    ProfilePoint {
+        radius,
+        y,
+        normal,
+        v,
+    }
+}
+
+fn push_vertex(mesh: &mut Mesh, position: [f32; 3], normal: [f32; 3], texcoord: [f32; 2]) -> u32 {
+    mesh.positions.push(position);
+    mesh.normals.push(normal);
+    mesh.texcoords.push(texcoord);
+    (mesh.positions.len() - 1) as u32
+}
+
+//adds the triangle facing the way its vertex normals point, dropping it if it has no area
+fn push_triangle(mesh: &mut Mesh, a: u32, b: u32, c: u32) {
+    let position = |i: u32| na::Vector3::from(mesh.positions[i as usize]);
+    let normal = |i: u32| na::Vector3::from(mesh.normals[i as usize]);
+    let face = (position(b) - position(a)).cross(&(position(c) - position(a)));
+    if face.norm_squared() < 1e-12 {
+        return;
+    }
+    if face.dot(&(normal(a) + normal(b) + normal(c))) < 0.0 {
+        mesh.indices.extend([a, c, b]);
+    } else {
+        mesh.indices.extend([a, b, c]);
+    }
+}
+
+//rotates every section of the outline around the y axis; consecutive points of a section are
+//joined, separate sections are not, which gives hard edges between them
+fn lathe(sections: &[Vec<ProfilePoint>], segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let mut mesh = Mesh::default();
+    for section in sections {
+        let mut rings = vec![];
+        for p in section {
+            //the seam is duplicated so u can run from 0 to 1
+            let ring: Vec<u32> = (0..=segments)
+                .map(|j| {
+                    let u = j as f32 / segments as f32;
+                    let (sin, cos) = (u * TAU).sin_cos();
+                    let normal =
+                        na::Vector3::new(p.normal[0] * cos, p.normal[1], p.normal[0] * sin)
+                            .normalize();
+                    push_vertex(
+                        &mut mesh,
+                        [p.radius * cos, p.y, p.radius * sin],
+                        normal.into(),
+                        [u, p.v],
+                    )
+                })
+                .collect();
+            rings.push(ring);
+        }
+        for pair in rings.windows(2) {
+            for j in 0..segments as usize {
+                let (a, b, c, d) = (pair[0][j], pair[0][j + 1], pair[1][j + 1], pair[1][j]);
+                push_triangle(&mut mesh, a, b, c);
+                push_triangle(&mut mesh, a, c, d);
+            }
+        }
+    }
+    mesh.generate_tangents();
+    mesh
+}
+
+//`rings` is the number of bands from pole to pole
+pub(crate) fn uv_sphere(segments: u32, rings: u32) -> Mesh {
+    let rings = rings.max(2);
+    let outline = (0..=rings)
+        .map(|i| {
+            let v = i as f32 / rings as f32;
+            let (sin, cos) = (v * PI).sin_cos();
+            point(sin, -cos, [sin, -cos], v)
+        })
+        .collect();
+    lathe(&[outline], segments)
+}
+
+//an icosahedron with every triangle split into four `subdivisions` times
+pub(crate) fn icosphere(subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions: Vec<na::Vector3<f32>> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(|&p| na::Vector3::from(p).normalize())
+    .collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+    for _ in 0..subdivisions {
+        let mut midpoints = std::collections::HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let m = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(m);
+                (positions.len() - 1) as u32
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+    let mut mesh = Mesh::default();
+    for p in &positions {
+        let u = 0.5 + p.z.atan2(p.x) / TAU;
+        let v = p.y.clamp(-1.0, 1.0).acos() / PI;
+        push_vertex(&mut mesh, (*p).into(), (*p).into(), [u, 1.0 - v]);
+    }
+    for [a, b, c] in triangles {
+        //triangles straddling the seam get copies of their low-u corners moved past 1, so the
+        //texture does not run backwards across them
+        let us = [a, b, c].map(|i| mesh.texcoords[i as usize][0]);
+        let max = us.iter().cloned().fold(0.0, f32::max);
+        let [a, b, c] = [a, b, c].map(|i| {
+            let [u, v] = mesh.texcoords[i as usize];
+            if max - u > 0.5 {
+                let (position, normal) = (mesh.positions[i as usize], mesh.normals[i as usize]);
+                push_vertex(&mut mesh, position, normal, [u + 1.0, v])
+            } else {
+                i
+            }
+        });
+        push_triangle(&mut mesh, a, b, c);
+    }
+    mesh.generate_tangents();
+    mesh
+}
+
+//radius 1, from y=-1 to y=1, closed at both ends
+pub(crate) fn cylinder(segments: u32) -> Mesh {
+    lathe(
+        &[
+            vec![
+                point(0.0, -1.0, [0.0, -1.0], 0.0),
+                point(1.0, -1.0, [0.0, -1.0], 0.0),
+            ],
+            vec![
+                point(1.0, -1.0, [1.0, 0.0], 0.0),
+                point(1.0, 1.0, [1.0, 0.0], 1.0),
+            ],
+            vec![
+                point(1.0, 1.0, [0.0, 1.0], 1.0),
+                point(0.0, 1.0, [0.0, 1.0], 1.0),
+            ],
+        ],
+        segments,
+    )
+}
+
+//base of radius 1 at y=-1, tip at y=1
+pub(crate) fn cone(segments: u32) -> Mesh {
+    //the side rises 2 over a run of 1, so its normal leans (2, 1)
+    let side = [2.0 / 5f32.sqrt(), 1.0 / 5f32.sqrt()];
+    lathe(
+        &[
+            vec![
+                point(0.0, -1.0, [0.0, -1.0], 0.0),
+                point(1.0, -1.0, [0.0, -1.0], 0.0),
+            ],
+            vec![point(1.0, -1.0, side, 0.0), point(0.0, 1.0, side, 1.0)],
+        ],
+        segments,
+    )
+}
+
+//radius 1, with `length` being the straight part between the two hemispheres
+pub(crate) fn capsule(segments: u32, rings: u32, length: f32) -> Mesh {
+    let rings = rings.max(1);
+    let half = length / 2.0;
+    let total = PI + length;
+    let mut outline = vec![];
+    //rings per hemisphere, from the bottom pole up
+    for i in 0..=rings {
+        let angle = i as f32 / rings as f32 * PI / 2.0;
+        let (sin, cos) = angle.sin_cos();
+        outline.push(point(sin, -half - cos, [sin, -cos], angle / total));
+    }
+    for i in 0..=rings {
+        let angle = PI / 2.0 + i as f32 / rings as f32 * PI / 2.0;
+        let (sin, cos) = angle.sin_cos();
+        outline.push(point(
+            sin,
+            half - cos,
+            [sin, -cos],
+            (angle + length) / total,
+        ));
+    }
+    lathe(&[outline], segments)
+}
+
+//a square from -1 to 1 in x and z facing -y, which is up, split into `subdivisions` squares per side
+pub(crate) fn grid(subdivisions: u32) -> Mesh {
+    let subdivisions = subdivisions.max(1);
+    let mut mesh = Mesh::default();
+    let stride = subdivisions + 1;
+    for i in 0..=subdivisions {
+        for j in 0..=subdivisions {
+            let (u, v) = (
+                j as f32 / subdivisions as f32,
+                i as f32 / subdivisions as f32,
+            );
+            push_vertex(
+                &mut mesh,
+                [u * 2.0 - 1.0, 0.0, v * 2.0 - 1.0],
+                [0.0, -1.0, 0.0],
+                [u, v],
+            );
+        }
+    }
+    for i in 0..subdivisions {
+        for j in 0..subdivisions {
+            let a = i * stride + j;
+            push_triangle(&mut mesh, a, a + 1, a + stride + 1);
+            push_triangle(&mut mesh, a, a + stride + 1, a + stride);
+        }
+    }
+    mesh.generate_tangents();
+    mesh
+}
+
+//a ring of radius 1 around y with a tube of `minor_radius`
+pub(crate) fn torus(segments: u32, rings: u32, minor_radius: f32) -> Mesh {
+    let rings = rings.max(3);
+    let outline = (0..=rings)
+        .map(|i| {
+            let v = i as f32 / rings as f32;
+            let (sin, cos) = (v * TAU).sin_cos();
+            point(1.0 + minor_radius * cos, minor_radius * sin, [cos, sin], v)
+        })
+        .collect();
+    lathe(&[outline], segments)
+}
+
+//from the origin to y=1, for axes and direction markers
+pub(crate) fn arrow(segments: u32) -> Mesh {
+    const SHAFT: f32 = 0.03;
+    const HEAD: f32 = 0.08;
+    const HEAD_START: f32 = 0.8;
+    let slope = (1.0 - HEAD_START) / HEAD;
+    let head_normal = [
+        slope / (1.0 + slope * slope).sqrt(),
+        1.0 / (1.0 + slope * slope).sqrt(),
+    ];
+    lathe(
+        &[
+            vec![
+                point(0.0, 0.0, [0.0, -1.0], 0.0),
+                point(SHAFT, 0.0, [0.0, -1.0], 0.0),
+            ],
+            vec![
+                point(SHAFT, 0.0, [1.0, 0.0], 0.0),
+                point(SHAFT, HEAD_START, [1.0, 0.0], HEAD_START),
+            ],
+            vec![
+                point(SHAFT, HEAD_START, [0.0, -1.0], HEAD_START),
+                point(HEAD, HEAD_START, [0.0, -1.0], HEAD_START),
+            ],
+            vec![
+                point(HEAD, HEAD_START, head_normal, HEAD_START),
+                point(0.0, 1.0, head_normal, 1.0),
+            ],
+        ],
+        segments,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Vec<(&'static str, Mesh)> {
+        vec![
+            ("uv_sphere", uv_sphere(16, 8)),
+            ("icosphere", icosphere(2)),
+            ("cylinder", cylinder(12)),
+            ("cone", cone(12)),
+            ("capsule", capsule(12, 4, 1.0)),
+            ("grid", grid(4)),
+            ("torus", torus(16, 8, 0.25)),
+            ("arrow", arrow(12)),
+        ]
+    }
+
+    #[test]
+    fn attributes_are_complete_and_normals_unit_length() {
+        for (name, mesh) in all() {
+            let amount = mesh.positions.len();
+            assert!(!mesh.indices.is_empty(), "{}", name);
+            assert_eq!(mesh.normals.len(), amount, "{}", name);
+            assert_eq!(mesh.texcoords.len(), amount, "{}", name);
+            assert_eq!(mesh.tangents.len(), amount, "{}", name);
+            assert!(
+                mesh.indices.iter().all(|&i| (i as usize) < amount),
+                "{}",
+                name
+            );
+            for normal in &mesh.normals {
+                let length = na::Vector3::from(*normal).norm();
+                assert!((length - 1.0).abs() < 1e-4, "{}: {:?}", name, normal);
+            }
+        }
+    }
+
+    #[test]
+    fn triangles_face_outwards() {
+        for (name, mesh) in all() {
+            for triangle in mesh.indices.chunks_exact(3) {
+                let [a, b, c] =
+                    [0, 1, 2].map(|k| na::Vector3::from(mesh.positions[triangle[k] as usize]));
+                let face = (b - a).cross(&(c - a));
+                let normal: na::Vector3<f32> = triangle
+                    .iter()
+                    .map(|&i| na::Vector3::from(mesh.normals[i as usize]))
+                    .sum();
+                assert!(face.dot(&normal) > 0.0, "{}: {:?}", name, triangle);
+            }
+            //and for the closed convex ones, away from the centre as well
+            if ["uv_sphere", "icosphere", "cylinder", "cone", "capsule"].contains(&name) {
+                for triangle in mesh.indices.chunks_exact(3) {
+                    let [a, b, c] =
+                        [0, 1, 2].map(|k| na::Vector3::from(mesh.positions[triangle[k] as usize]));
+                    assert!((b - a).cross(&(c - a)).dot(&(a + b + c)) > 0.0, "{}", name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tessellation_controls_the_triangle_count() {
+        assert!(uv_sphere(32, 16).indices.len() > uv_sphere(8, 4).indices.len());
+        assert_eq!(icosphere(0).indices.len(), 20 * 3);
+        assert_eq!(icosphere(1).indices.len(), 80 * 3);
+        assert_eq!(grid(3).indices.len(), 3 * 3 * 2 * 3);
+    }
+}
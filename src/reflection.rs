@@ -0,0 +1,503 @@
+//what the pipeline needs to know about its shaders, read back from their SPIR-V so the vertex input
+//and the layouts cannot drift away from the GLSL
+use crate::vertexlayout::BufferLayout;
+use ash::vk;
+
+#[derive(Debug)]
+pub(crate) enum ReflectionError {
+    Parse(naga::front::spv::Error),
+    Invalid(String),
+    MissingEntryPoint(vk::ShaderStageFlags),
+    //a vertex input that is not a scalar or vector of 32 bit numbers
+    UnsupportedInput {
+        location: u32,
+    },
+    UnsupportedResource {
+        set: u32,
+        binding: u32,
+    },
+    //the stages disagree about what is bound there
+    ConflictingResource {
+        set: u32,
+        binding: u32,
+    },
+    MissingAttribute {
+        location: u32,
+        format: vk::Format,
+    },
+    AttributeMismatch {
+        location: u32,
+        shader: vk::Format,
+        rust: vk::Format,
+    },
+}
+impl std::fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReflectionError::Parse(e) => write!(f, "could not read SPIR-V: {}", e),
+            ReflectionError::Invalid(message) => write!(f, "invalid SPIR-V: {}", message),
+            ReflectionError::MissingEntryPoint(stage) => {
+                write!(f, "no {:?} entry point in the SPIR-V", stage)
+            }
+            ReflectionError::UnsupportedInput { location } => {
+                write!(f, "vertex input at location {} has an unsupported type", location)
+            }
+            ReflectionError::UnsupportedResource { set, binding } => write!(
+                f,
+                "set {} binding {} has an unsupported resource type",
+                set, binding
+            ),
+            ReflectionError::ConflictingResource { set, binding } => write!(
+                f,
+                "the shader stages disagree about set {} binding {}",
+                set, binding
+            ),
+            ReflectionError::MissingAttribute { location, format } => write!(
+                f,
+                "the vertex shader reads a {:?} at location {}, but no vertex or instance field provides it",
+                format, location
+            ),
+            ReflectionError::AttributeMismatch {
+                location,
+                shader,
+                rust,
+            } => write!(
+                f,
+                "the vertex shader reads a {:?} at location {}, but the field there is a {:?}",
+                shader, location, rust
+            ),
+        }
+    }
+}
+impl std::error::Error for ReflectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReflectionError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<naga::front::spv::Error> for ReflectionError {
+    fn from(e: naga::front::spv::Error) -> Self {
+        ReflectionError::Parse(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct VertexInput {
+    pub(crate) location: u32,
+    pub(crate) format: vk::Format,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct DescriptorBinding {
+    pub(crate) set: u32,
+    pub(crate) binding: u32,
+    pub(crate) descriptor_type: vk::DescriptorType,
+    pub(crate) count: u32,
+    pub(crate) stages: vk::ShaderStageFlags,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Reflection {
+    //sorted by location
+    pub(crate) vertex_inputs: Vec<VertexInput>,
+    //sorted by set, then binding
+    pub(crate) descriptor_bindings: Vec<DescriptorBinding>,
+    //all stages share one range starting at 0, zero bytes if there are no push constants
+    pub(crate) push_constant_size: u32,
+    pub(crate) push_constant_stages: vk::ShaderStageFlags,
+}
+
+impl Reflection {
+    //every stage's SPIR-V needs an entry point for that stage
+    pub(crate) fn reflect(
+        stages: &[(vk::ShaderStageFlags, &[u32])],
+    ) -> Result<Reflection, ReflectionError> {
+        let mut reflection = Reflection::default();
+        for &(stage, code) in stages {
+            reflection.add_stage(stage, code)?;
+        }
+        reflection.vertex_inputs.sort_by_key(|input| input.location);
+        reflection
+            .descriptor_bindings
+            .sort_by_key(|binding| (binding.set, binding.binding));
+        Ok(reflection)
+    }
+
+    fn add_stage(
+        &mut self,
+        stage: vk::ShaderStageFlags,
+        code: &[u32],
+    ) -> Result<(), ReflectionError> {
+        let bytes: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let module = naga::front::spv::parse_u8_slice(&bytes, &Default::default())?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|e| ReflectionError::Invalid(e.as_inner().to_string()))?;
+        let (index, entry_point) = module
+            .entry_points
+            .iter()
+            .enumerate()
+            .find(|(_, entry_point)| stage_flags(entry_point.stage) == stage)
+            .ok_or(ReflectionError::MissingEntryPoint(stage))?;
+
+        if stage == vk::ShaderStageFlags::VERTEX {
+            for argument in &entry_point.function.arguments {
+                if let Some(naga::Binding::Location { location, .. }) = argument.binding {
+                    let format = vertex_format(&module.types[argument.ty].inner)
+                        .ok_or(ReflectionError::UnsupportedInput { location })?;
+                    self.vertex_inputs.push(VertexInput { location, format });
+                }
+            }
+        }
+
+        //only what the entry point actually uses
+        let used = info.get_entry_point(index);
+        for (handle, variable) in module.global_variables.iter() {
+            if used[handle].is_empty() {
+                continue;
+            }
+            if variable.space == naga::AddressSpace::PushConstant {
+                let size = module.types[variable.ty].inner.size(module.to_ctx());
+                self.push_constant_size = self.push_constant_size.max(size);
+                self.push_constant_stages |= stage;
+                continue;
+            }
+            let Some(naga::ResourceBinding { group, binding }) = variable.binding else {
+                continue;
+            };
+            let (descriptor_type, count) =
+                descriptor_type(&module, variable).ok_or(ReflectionError::UnsupportedResource {
+                    set: group,
+                    binding,
+                })?;
+            match self
+                .descriptor_bindings
+                .iter_mut()
+                .find(|existing| existing.set == group && existing.binding == binding)
+            {
+                Some(existing)
+                    if existing.descriptor_type == descriptor_type && existing.count == count =>
+                {
+                    existing.stages |= stage
+                }
+                Some(_) => {
+                    return Err(ReflectionError::ConflictingResource {
+                        set: group,
+                        binding,
+                    })
+                }
+                None => self.descriptor_bindings.push(DescriptorBinding {
+                    set: group,
+                    binding,
+                    descriptor_type,
+                    count,
+                    stages: stage,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    //one list per set, from set 0 to the highest one used
+    pub(crate) fn descriptor_set_layout_bindings(
+        &self,
+    ) -> Vec<Vec<vk::DescriptorSetLayoutBinding>> {
+        let amount_of_sets = self
+            .descriptor_bindings
+            .iter()
+            .map(|binding| binding.set + 1)
+            .max()
+            .unwrap_or(0);
+        (0..amount_of_sets)
+            .map(|set| {
+                self.descriptor_bindings
+                    .iter()
+                    .filter(|binding| binding.set == set)
+                    .map(|binding| {
+                        vk::DescriptorSetLayoutBinding::builder()
+                            .binding(binding.binding)
+                            .descriptor_type(binding.descriptor_type)
+                            .descriptor_count(binding.count)
+                            .stage_flags(binding.stages)
+                            .build()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub(crate) fn push_constant_ranges(&self) -> Vec<vk::PushConstantRange> {
+        if self.push_constant_size == 0 {
+            return vec![];
+        }
+        vec![vk::PushConstantRange {
+            stage_flags: self.push_constant_stages,
+            offset: 0,
+            size: self.push_constant_size,
+        }]
+    }
+
+    //whether a pipeline layout made for `layout` has everything these shaders use
+    pub(crate) fn fits_into(&self, layout: &Reflection) -> bool {
+        let bindings_fit = self.descriptor_bindings.iter().all(|used| {
+            layout.descriptor_bindings.iter().any(|provided| {
+                provided.set == used.set
+                    && provided.binding == used.binding
+                    && provided.descriptor_type == used.descriptor_type
+                    && provided.count == used.count
+                    && provided.stages.contains(used.stages)
+            })
+        });
+        bindings_fit
+            && (self.push_constant_size == 0
+                || self.push_constant_size <= layout.push_constant_size
+                    && layout
+                        .push_constant_stages
+                        .contains(self.push_constant_stages))
+    }
+
+    //`buffers` describes the vertex buffer bindings in order, whose fields take up consecutive
+    //locations; fields the shader does not read are left out
+    pub(crate) fn vertex_attributes(
+        &self,
+        buffers: &[BufferLayout],
+    ) -> Result<Vec<vk::VertexInputAttributeDescription>, ReflectionError> {
+        let mut by_location = vec![];
+        for (binding, buffer) in buffers.iter().enumerate() {
+            for attribute in &buffer.attributes {
+                by_location.push((binding as u32, attribute));
+            }
+        }
+        self.vertex_inputs
+            .iter()
+            .map(|input| {
+                let &(binding, attribute) = by_location.get(input.location as usize).ok_or(
+                    ReflectionError::MissingAttribute {
+                        location: input.location,
+                        format: input.format,
+                    },
+                )?;
+                if attribute.format != input.format {
+                    return Err(ReflectionError::AttributeMismatch {
+                        location: input.location,
+                        shader: input.format,
+                        rust: attribute.format,
+                    });
+                }
+                Ok(vk::VertexInputAttributeDescription {
+                    binding,
+                    location: input.location,
+                    offset: attribute.offset,
+                    format: attribute.format,
+                })
+            })
+            .collect()
+    }
+}
+
+fn stage_flags(stage: naga::ShaderStage) -> vk::ShaderStageFlags {
+    match stage {
+        naga::ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
+        naga::ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+        naga::ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+    }
+}
+
+fn vertex_format(inner: &naga::TypeInner) -> Option<vk::Format> {
+    use naga::{ScalarKind, VectorSize};
+    let (size, scalar) = match *inner {
+        naga::TypeInner::Scalar(scalar) => (1, scalar),
+        naga::TypeInner::Vector { size, scalar } => (
+            match size {
+                VectorSize::Bi => 2,
+                VectorSize::Tri => 3,
+                VectorSize::Quad => 4,
+            },
+            scalar,
+        ),
+        _ => return None,
+    };
+    if scalar.width != 4 {
+        return None;
+    }
+    Some(match (scalar.kind, size) {
+        (ScalarKind::Float, 1) => vk::Format::R32_SFLOAT,
+        (ScalarKind::Float, 2) => vk::Format::R32G32_SFLOAT,
+        (ScalarKind::Float, 3) => vk::Format::R32G32B32_SFLOAT,
+        (ScalarKind::Float, _) => vk::Format::R32G32B32A32_SFLOAT,
+        (ScalarKind::Sint, 1) => vk::Format::R32_SINT,
+        (ScalarKind::Sint, 2) => vk::Format::R32G32_SINT,
+        (ScalarKind::Sint, 3) => vk::Format::R32G32B32_SINT,
+        (ScalarKind::Sint, _) => vk::Format::R32G32B32A32_SINT,
+        (ScalarKind::Uint, 1) => vk::Format::R32_UINT,
+        (ScalarKind::Uint, 2) => vk::Format::R32G32_UINT,
+        (ScalarKind::Uint, 3) => vk::Format::R32G32B32_UINT,
+        (ScalarKind::Uint, _) => vk::Format::R32G32B32A32_UINT,
+        _ => return None,
+    })
+}
+
+//the type and the number of descriptors
+fn descriptor_type(
+    module: &naga::Module,
+    variable: &naga::GlobalVariable,
+) -> Option<(vk::DescriptorType, u32)> {
+    let (inner, count) = match module.types[variable.ty].inner {
+        naga::TypeInner::BindingArray {
+            base,
+            size: naga::ArraySize::Constant(size),
+        } => (&module.types[base].inner, size.get()),
+        ref inner => (inner, 1),
+    };
+    let descriptor_type = match (variable.space, inner) {
+        (naga::AddressSpace::Uniform, _) => vk::DescriptorType::UNIFORM_BUFFER,
+        (naga::AddressSpace::Storage { .. }, _) => vk::DescriptorType::STORAGE_BUFFER,
+        (naga::AddressSpace::Handle, naga::TypeInner::Sampler { .. }) => {
+            vk::DescriptorType::SAMPLER
+        }
+        (
+            naga::AddressSpace::Handle,
+            naga::TypeInner::Image {
+                class: naga::ImageClass::Storage { .. },
+                ..
+            },
+        ) => vk::DescriptorType::STORAGE_IMAGE,
+        (naga::AddressSpace::Handle, naga::TypeInner::Image { .. }) => {
+            vk::DescriptorType::SAMPLED_IMAGE
+        }
+        _ => return None,
+    };
+    Some((descriptor_type, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ColouredVertex, InstanceData, Model, Transform, VertexData};
+    use crate::shaders::{compile_source, ShaderSet};
+    use std::path::Path;
+
+    fn vertex_shader(source: &str) -> Vec<u32> {
+        compile_source(Path::new("test.vert"), source, naga::ShaderStage::Vertex).unwrap()
+    }
+
+    #[test]
+    fn shipped_shaders_match_the_rust_layouts() {
+        let shaders =
+            ShaderSet::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders")).unwrap();
+        let reflection = Reflection::reflect(&[
+            (vk::ShaderStageFlags::VERTEX, &shaders.vertex),
+            (vk::ShaderStageFlags::FRAGMENT, &shaders.fragment),
+        ])
+        .unwrap();
+        let attributes = reflection
+            .vertex_attributes(&Model::<VertexData, InstanceData>::buffer_layouts())
+            .unwrap();
+        let offsets: Vec<(u32, u32, u32)> = attributes
+            .iter()
+            .map(|a| (a.binding, a.location, a.offset))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                (0, 0, 0),
+                (0, 1, 12),
+                (1, 2, 0),
+                (1, 3, 16),
+                (1, 4, 32),
+                (1, 5, 48),
+                (1, 6, 64)
+            ]
+        );
+        let sets = reflection.descriptor_set_layout_bindings();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(
+            sets[0][0].stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!(sets[0][1].stage_flags, vk::ShaderStageFlags::FRAGMENT);
+        assert!(reflection.push_constant_ranges().is_empty());
+    }
+
+    #[test]
+    fn group_shaders_fit_into_the_main_layouts() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+        let main = ShaderSet::load(&root).unwrap().reflect().unwrap();
+        let group = ShaderSet::load(&root.join("vertexcolour"))
+            .unwrap()
+            .reflect()
+            .unwrap();
+        assert!(group.fits_into(&main));
+        assert!(!main.fits_into(&group));
+        assert!(group
+            .vertex_attributes(&Model::<ColouredVertex, Transform>::buffer_layouts())
+            .is_ok());
+        //the main shaders read a colour per instance
+        assert!(matches!(
+            main.vertex_attributes(&Model::<ColouredVertex, Transform>::buffer_layouts()),
+            Err(ReflectionError::MissingAttribute { location: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn mismatched_inputs_are_reported() {
+        let code = vertex_shader(
+            "#version 450\nlayout (location=1) in vec2 normal;\n\
+             void main() { gl_Position = vec4(normal, 0.0, 1.0); }\n",
+        );
+        let reflection = Reflection::reflect(&[(vk::ShaderStageFlags::VERTEX, &code)]).unwrap();
+        let mut vertices = BufferLayout::of::<VertexData>();
+        assert!(matches!(
+            reflection.vertex_attributes(&[vertices.clone()]),
+            Err(ReflectionError::AttributeMismatch {
+                location: 1,
+                shader: vk::Format::R32G32_SFLOAT,
+                rust: vk::Format::R32G32B32_SFLOAT,
+            })
+        ));
+        vertices.attributes.truncate(1);
+        assert!(matches!(
+            reflection.vertex_attributes(&[vertices]),
+            Err(ReflectionError::MissingAttribute { location: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn push_constants_get_a_range() {
+        let code = vertex_shader(
+            "#version 450\n\
+             layout (push_constant) uniform Constants { mat4 transform; float scale; } constants;\n\
+             void main() { gl_Position = constants.transform * vec4(constants.scale); }\n",
+        );
+        let ranges = Reflection::reflect(&[(vk::ShaderStageFlags::VERTEX, &code)])
+            .unwrap()
+            .push_constant_ranges();
+        assert_eq!(ranges.len(), 1);
+        //the block is padded to the 16 byte alignment of the matrix
+        assert_eq!(ranges[0].size, 80);
+        assert_eq!(ranges[0].stage_flags, vk::ShaderStageFlags::VERTEX);
+    }
+
+    #[test]
+    fn culling_shader_uses_five_storage_buffers() {
+        let code = crate::shaders::compile(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders/culling/cull.comp"),
+        )
+        .unwrap();
+        let reflection = Reflection::reflect(&[(vk::ShaderStageFlags::COMPUTE, &code)]).unwrap();
+        assert_eq!(reflection.descriptor_bindings.len(), 5);
+        assert!(reflection
+            .descriptor_bindings
+            .iter()
+            .all(|binding| binding.set == 0
+                && binding.descriptor_type == vk::DescriptorType::STORAGE_BUFFER
+                && binding.stages == vk::ShaderStageFlags::COMPUTE));
+        //six planes, the sphere, the amount and the pass, padded to a multiple of 16 bytes
+        assert_eq!(reflection.push_constant_size, 128);
+    }
+}
@@ -1,9 +1,12 @@
-use crate::Swapchain;
+use crate::reflection::{Reflection, ReflectionError};
+use crate::shaders::{ShaderError, ShaderSet};
+use crate::vertexlayout::BufferLayout;
 use ash::vk;
 
 pub(crate) fn init_renderpass(
     logical_device: &ash::Device,
     format: vk::Format,
+    final_layout: vk::ImageLayout,
 ) -> Result<vk::RenderPass, vk::Result> {
     let attachments = [
         vk::AttachmentDescription::builder()
@@ -13,7 +16,7 @@ pub(crate) fn init_renderpass(
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .final_layout(final_layout)
             .samples(vk::SampleCountFlags::TYPE_1)
             .build(),
         vk::AttachmentDescription::builder()
@@ -57,10 +60,146 @@ pub(crate) fn init_renderpass(
     Ok(renderpass)
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum BlendMode {
+    //overwrites what is behind
+    Opaque,
+    //mixes by the alpha of the fragment
+    Alpha,
+    //adds onto what is behind, for glows and the like
+    Additive,
+}
+
+//everything about how a model is drawn that is baked into its pipeline
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PipelineState {
+    pub(crate) topology: vk::PrimitiveTopology,
+    //LINE and POINT need the fillModeNonSolid device feature
+    pub(crate) polygon_mode: vk::PolygonMode,
+    pub(crate) cull_mode: vk::CullModeFlags,
+    pub(crate) depth_test: bool,
+    pub(crate) depth_write: bool,
+    pub(crate) blend: BlendMode,
+    //a directory with a shader.vert and shader.frag to draw with instead of the usual ones
+    pub(crate) shaders: Option<std::path::PathBuf>,
+}
+impl Default for PipelineState {
+    fn default() -> Self {
+        PipelineState {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_test: true,
+            depth_write: true,
+            blend: BlendMode::Opaque,
+            shaders: None,
+        }
+    }
+}
+impl PipelineState {
+    pub(crate) fn wireframe() -> PipelineState {
+        PipelineState {
+            polygon_mode: vk::PolygonMode::LINE,
+            ..Default::default()
+        }
+    }
+    //every two indices make a line
+    pub(crate) fn lines() -> PipelineState {
+        PipelineState {
+            topology: vk::PrimitiveTopology::LINE_LIST,
+            ..Default::default()
+        }
+    }
+    pub(crate) fn points() -> PipelineState {
+        PipelineState {
+            topology: vk::PrimitiveTopology::POINT_LIST,
+            ..Default::default()
+        }
+    }
+    //tested against the depth buffer but not written into it, so it does not hide what is drawn
+    //after it
+    pub(crate) fn transparent() -> PipelineState {
+        PipelineState {
+            blend: BlendMode::Alpha,
+            depth_write: false,
+            ..Default::default()
+        }
+    }
+    //such models are drawn after the opaque ones, which they need to blend with
+    pub(crate) fn is_transparent(&self) -> bool {
+        self.blend != BlendMode::Opaque
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum PipelineError {
+    Vulkan(vk::Result),
+    Shader(ShaderError),
+    Reflection(ReflectionError),
+    //the device feature, by its name in the Vulkan specification
+    MissingFeature(&'static str),
+    //descriptor sets are allocated for the old layouts, so those have to stay
+    LayoutChanged,
+    //shaders of a model group that use descriptors the main shaders do not have
+    IncompatibleResources,
+}
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PipelineError::Vulkan(e) => write!(f, "could not create the pipeline: {}", e),
+            PipelineError::Shader(e) => write!(f, "{}", e),
+            PipelineError::Reflection(e) => write!(f, "shaders do not fit the pipeline: {}", e),
+            PipelineError::MissingFeature(feature) => {
+                write!(f, "the pipeline needs the {} device feature", feature)
+            }
+            PipelineError::LayoutChanged => write!(
+                f,
+                "the shaders now use new descriptors or push constants, restart to apply them"
+            ),
+            PipelineError::IncompatibleResources => write!(
+                f,
+                "the shaders use descriptors or push constants that shader.vert and shader.frag do not"
+            ),
+        }
+    }
+}
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::Vulkan(e) => Some(e),
+            PipelineError::Shader(e) => Some(e),
+            PipelineError::Reflection(e) => Some(e),
+            PipelineError::MissingFeature(_)
+            | PipelineError::LayoutChanged
+            | PipelineError::IncompatibleResources => None,
+        }
+    }
+}
+impl From<vk::Result> for PipelineError {
+    fn from(e: vk::Result) -> Self {
+        PipelineError::Vulkan(e)
+    }
+}
+impl From<ShaderError> for PipelineError {
+    fn from(e: ShaderError) -> Self {
+        PipelineError::Shader(e)
+    }
+}
+impl From<ReflectionError> for PipelineError {
+    fn from(e: ReflectionError) -> Self {
+        PipelineError::Reflection(e)
+    }
+}
+
 pub(crate) struct Pipeline {
     pub(crate) pipeline: vk::Pipeline,
     pub(crate) layout: vk::PipelineLayout,
     pub(crate) descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    //the descriptors and push constants the layouts were made for
+    resources: Reflection,
+    //the vertex buffer bindings, the first one per vertex and the others per instance
+    buffers: Vec<BufferLayout>,
+    pub(crate) state: PipelineState,
 }
 
 impl Pipeline {
@@ -72,173 +211,322 @@ impl Pipeline {
         logical_device.destroy_pipeline_layout(self.layout, None);
     }
 
+    //the descriptor set layouts and push constant ranges follow from the shaders, the vertex input
+    //from matching them against `buffers`, usually Model::buffer_layouts
     pub(crate) fn init(
         logical_device: &ash::Device,
-        swapchain: &Swapchain,
         renderpass: &vk::RenderPass,
-    ) -> Result<Pipeline, vk::Result> {
-        let vertexshader_createinfo = vk::ShaderModuleCreateInfo::builder().code(
-            vk_shader_macros::include_glsl!("./shaders/shader.vert", kind: vert),
-        );
-        let vertexshader_module =
-            unsafe { logical_device.create_shader_module(&vertexshader_createinfo, None)? };
-        let fragmentshader_createinfo = vk::ShaderModuleCreateInfo::builder()
-            .code(vk_shader_macros::include_glsl!("./shaders/shader.frag"));
-        let fragmentshader_module =
-            unsafe { logical_device.create_shader_module(&fragmentshader_createinfo, None)? };
-        let mainfunctionname = std::ffi::CString::new("main").unwrap();
-        let vertexshader_stage = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::VERTEX)
-            .module(vertexshader_module)
-            .name(&mainfunctionname);
-        let fragmentshader_stage = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(fragmentshader_module)
-            .name(&mainfunctionname);
-        let shader_stages = vec![vertexshader_stage.build(), fragmentshader_stage.build()];
-        let vertex_attrib_descs = [
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 0,
-                offset: 0,
-                format: vk::Format::R32G32B32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 1,
-                offset: 0,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 2,
-                offset: 16,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 3,
-                offset: 32,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 4,
-                offset: 48,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 5,
-                offset: 64,
-                format: vk::Format::R32G32B32_SFLOAT,
-            },
-        ];
-        let vertex_binding_descs = [
-            vk::VertexInputBindingDescription {
-                binding: 0,
-                stride: 12,
-                input_rate: vk::VertexInputRate::VERTEX,
-            },
-            vk::VertexInputBindingDescription {
-                binding: 1,
-                stride: 76,
-                input_rate: vk::VertexInputRate::INSTANCE,
-            },
-        ];
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_attribute_descriptions(&vertex_attrib_descs)
-            .vertex_binding_descriptions(&vertex_binding_descs);
-        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
-        let viewports = [vk::Viewport {
-            x: 0.,
-            y: 0.,
-            width: swapchain.extent.width as f32,
-            height: swapchain.extent.height as f32,
-            min_depth: 0.,
-            max_depth: 1.,
-        }];
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain.extent,
-        }];
-
-        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
-        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
-            .line_width(1.0)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::NONE)
-            .polygon_mode(vk::PolygonMode::FILL);
-        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
-            .blend_enable(true)
-            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .alpha_blend_op(vk::BlendOp::ADD)
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
-            .build()];
-        let colourblend_info =
-            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
-        let descriptorset_layout_binding_descs = [vk::DescriptorSetLayoutBinding::builder()
-            .binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-            .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::VERTEX)
-            .build()];
-        let descriptorset_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
-            .bindings(&descriptorset_layout_binding_descs);
-        let descriptorsetlayout = unsafe {
-            logical_device.create_descriptor_set_layout(&descriptorset_layout_info, None)
-        }?;
-        let desclayouts = vec![descriptorsetlayout];
-        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&desclayouts);
+        cache: vk::PipelineCache,
+        shaders: &ShaderSet,
+        buffers: &[BufferLayout],
+        state: &PipelineState,
+    ) -> Result<Pipeline, PipelineError> {
+        let reflection = shaders.reflect()?;
+        let resources = reflection.clone();
+        Self::init_with_resources(
+            logical_device,
+            renderpass,
+            cache,
+            shaders,
+            &reflection,
+            resources,
+            buffers,
+            state,
+        )
+    }
+    //like init, but with layouts equal to those of `base`, so the descriptor sets made for it can
+    //be used with this pipeline as well; the shaders may only use what `base` provides
+    pub(crate) fn init_sharing_resources(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        cache: vk::PipelineCache,
+        shaders: &ShaderSet,
+        buffers: &[BufferLayout],
+        state: &PipelineState,
+        base: &Pipeline,
+    ) -> Result<Pipeline, PipelineError> {
+        let reflection = shaders.reflect()?;
+        if !reflection.fits_into(&base.resources) {
+            return Err(PipelineError::IncompatibleResources);
+        }
+        Self::init_with_resources(
+            logical_device,
+            renderpass,
+            cache,
+            shaders,
+            &reflection,
+            base.resources.clone(),
+            buffers,
+            state,
+        )
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn init_with_resources(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        cache: vk::PipelineCache,
+        shaders: &ShaderSet,
+        reflection: &Reflection,
+        resources: Reflection,
+        buffers: &[BufferLayout],
+        state: &PipelineState,
+    ) -> Result<Pipeline, PipelineError> {
+        let mut desclayouts = vec![];
+        for bindings in resources.descriptor_set_layout_bindings() {
+            let descriptorset_layout_info =
+                vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+            desclayouts.push(unsafe {
+                logical_device.create_descriptor_set_layout(&descriptorset_layout_info, None)
+            }?);
+        }
+        let push_constant_ranges = resources.push_constant_ranges();
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&desclayouts)
+            .push_constant_ranges(&push_constant_ranges);
         let pipelinelayout =
             unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
-        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
-            .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizer_info)
-            .multisample_state(&multisampler_info)
-            .depth_stencil_state(&depth_stencil_info)
-            .color_blend_state(&colourblend_info)
-            .layout(pipelinelayout)
-            .render_pass(*renderpass)
-            .subpass(0);
-        let graphicspipeline = unsafe {
-            logical_device
-                .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
-                    &[pipeline_info.build()],
-                    None,
-                )
-                .expect("A problem with the pipeline creation")
-        }[0];
-        unsafe {
-            logical_device.destroy_shader_module(fragmentshader_module, None);
-            logical_device.destroy_shader_module(vertexshader_module, None);
-        }
+        let graphicspipeline = create_graphics_pipeline(
+            logical_device,
+            renderpass,
+            cache,
+            pipelinelayout,
+            shaders,
+            reflection,
+            buffers,
+            state,
+        )?;
         Ok(Pipeline {
             pipeline: graphicspipeline,
             layout: pipelinelayout,
             descriptor_set_layouts: desclayouts,
+            resources,
+            buffers: buffers.to_vec(),
+            state: state.clone(),
+        })
+    }
+    //builds the pipeline again from new shaders, keeping the layouts; the old pipeline is handed
+    //back and has to be destroyed once no command buffer uses it anymore
+    pub(crate) fn replace_shaders(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        cache: vk::PipelineCache,
+        shaders: &ShaderSet,
+    ) -> Result<vk::Pipeline, PipelineError> {
+        let reflection = shaders.reflect()?;
+        if !reflection.fits_into(&self.resources) {
+            return Err(PipelineError::LayoutChanged);
+        }
+        let graphicspipeline = create_graphics_pipeline(
+            logical_device,
+            renderpass,
+            cache,
+            self.layout,
+            shaders,
+            &reflection,
+            &self.buffers,
+            &self.state,
+        )?;
+        Ok(std::mem::replace(&mut self.pipeline, graphicspipeline))
+    }
+}
+
+//a single compute shader, with layouts of its own following from its reflection
+pub(crate) struct ComputePipeline {
+    pub(crate) pipeline: vk::Pipeline,
+    pub(crate) layout: vk::PipelineLayout,
+    pub(crate) descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+}
+
+impl ComputePipeline {
+    pub(crate) unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        for dsl in &self.descriptor_set_layouts {
+            logical_device.destroy_descriptor_set_layout(*dsl, None);
+        }
+        logical_device.destroy_pipeline(self.pipeline, None);
+        logical_device.destroy_pipeline_layout(self.layout, None);
+    }
+
+    pub(crate) fn init(
+        logical_device: &ash::Device,
+        cache: vk::PipelineCache,
+        code: &[u32],
+    ) -> Result<ComputePipeline, PipelineError> {
+        let resources = Reflection::reflect(&[(vk::ShaderStageFlags::COMPUTE, code)])?;
+        let mut desclayouts = vec![];
+        let destroy_layouts = |desclayouts: &[vk::DescriptorSetLayout]| unsafe {
+            for dsl in desclayouts {
+                logical_device.destroy_descriptor_set_layout(*dsl, None);
+            }
+        };
+        for bindings in resources.descriptor_set_layout_bindings() {
+            let descriptorset_layout_info =
+                vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+            match unsafe {
+                logical_device.create_descriptor_set_layout(&descriptorset_layout_info, None)
+            } {
+                Ok(dsl) => desclayouts.push(dsl),
+                Err(e) => {
+                    destroy_layouts(&desclayouts);
+                    return Err(e.into());
+                }
+            }
+        }
+        let push_constant_ranges = resources.push_constant_ranges();
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&desclayouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipelinelayout =
+            match unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) } {
+                Ok(layout) => layout,
+                Err(e) => {
+                    destroy_layouts(&desclayouts);
+                    return Err(e.into());
+                }
+            };
+        let pipeline = create_compute_pipeline(logical_device, cache, pipelinelayout, code);
+        match pipeline {
+            Ok(pipeline) => Ok(ComputePipeline {
+                pipeline,
+                layout: pipelinelayout,
+                descriptor_set_layouts: desclayouts,
+            }),
+            Err(e) => {
+                unsafe { logical_device.destroy_pipeline_layout(pipelinelayout, None) };
+                destroy_layouts(&desclayouts);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+fn create_compute_pipeline(
+    logical_device: &ash::Device,
+    cache: vk::PipelineCache,
+    layout: vk::PipelineLayout,
+    code: &[u32],
+) -> Result<vk::Pipeline, vk::Result> {
+    let shader_createinfo = vk::ShaderModuleCreateInfo::builder().code(code);
+    let shader_module = unsafe { logical_device.create_shader_module(&shader_createinfo, None)? };
+    let mainfunctionname = std::ffi::CString::new("main").unwrap();
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(&mainfunctionname);
+    let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage.build())
+        .layout(layout);
+    let computepipeline =
+        unsafe { logical_device.create_compute_pipelines(cache, &[pipeline_info.build()], None) };
+    unsafe { logical_device.destroy_shader_module(shader_module, None) };
+    Ok(computepipeline.map_err(|(_, e)| e)?[0])
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_graphics_pipeline(
+    logical_device: &ash::Device,
+    renderpass: &vk::RenderPass,
+    cache: vk::PipelineCache,
+    layout: vk::PipelineLayout,
+    shaders: &ShaderSet,
+    reflection: &Reflection,
+    buffers: &[BufferLayout],
+    state: &PipelineState,
+) -> Result<vk::Pipeline, PipelineError> {
+    //checked before any Vulkan object exists
+    let vertex_attrib_descs = reflection.vertex_attributes(buffers)?;
+    let vertexshader_createinfo = vk::ShaderModuleCreateInfo::builder().code(&shaders.vertex);
+    let vertexshader_module =
+        unsafe { logical_device.create_shader_module(&vertexshader_createinfo, None)? };
+    let fragmentshader_createinfo = vk::ShaderModuleCreateInfo::builder().code(&shaders.fragment);
+    let fragmentshader_module =
+        unsafe { logical_device.create_shader_module(&fragmentshader_createinfo, None)? };
+    let mainfunctionname = std::ffi::CString::new("main").unwrap();
+    let vertexshader_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vertexshader_module)
+        .name(&mainfunctionname);
+    let fragmentshader_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(fragmentshader_module)
+        .name(&mainfunctionname);
+    let shader_stages = vec![vertexshader_stage.build(), fragmentshader_stage.build()];
+    let vertex_binding_descs: Vec<vk::VertexInputBindingDescription> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| vk::VertexInputBindingDescription {
+            binding: binding as u32,
+            stride: buffer.stride,
+            input_rate: if binding == 0 {
+                vk::VertexInputRate::VERTEX
+            } else {
+                vk::VertexInputRate::INSTANCE
+            },
         })
+        .collect();
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_attribute_descriptions(&vertex_attrib_descs)
+        .vertex_binding_descriptions(&vertex_binding_descs);
+    let input_assembly_info =
+        vk::PipelineInputAssemblyStateCreateInfo::builder().topology(state.topology);
+    //viewport and scissor are set per frame so the pipeline survives swapchain recreation
+    let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .line_width(1.0)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .cull_mode(state.cull_mode)
+        .polygon_mode(state.polygon_mode);
+    let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+    let destination_factor = match state.blend {
+        BlendMode::Opaque => vk::BlendFactor::ZERO,
+        BlendMode::Alpha => vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        BlendMode::Additive => vk::BlendFactor::ONE,
+    };
+    let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(state.blend != BlendMode::Opaque)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(destination_factor)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_alpha_blend_factor(destination_factor)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let colourblend_info =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(state.depth_test)
+        .depth_write_enable(state.depth_write)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly_info)
+        .viewport_state(&viewport_info)
+        .rasterization_state(&rasterizer_info)
+        .multisample_state(&multisampler_info)
+        .depth_stencil_state(&depth_stencil_info)
+        .color_blend_state(&colourblend_info)
+        .dynamic_state(&dynamic_state_info)
+        .layout(layout)
+        .render_pass(*renderpass)
+        .subpass(0);
+    let graphicspipeline =
+        unsafe { logical_device.create_graphics_pipelines(cache, &[pipeline_info.build()], None) };
+    unsafe {
+        logical_device.destroy_shader_module(fragmentshader_module, None);
+        logical_device.destroy_shader_module(vertexshader_module, None);
     }
+    Ok(graphicspipeline.map_err(|(_, e)| e)?[0])
 }
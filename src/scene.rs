@@ -0,0 +1,479 @@
+//scenes described in RON files, so they can be put together without recompiling; paths to mesh
+//files are relative to the scene file
+use crate::camera::Camera;
+use crate::gltfloader::GltfError;
+use crate::hotreload::{MeshFile, MeshFormat};
+use crate::model::{InstanceData, Model, VertexData};
+use crate::objloader::{ObjError, Skipped};
+use crate::slotmap::InstanceHandle;
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub(crate) enum SceneError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse(ron::error::SpannedError),
+    Write(ron::Error),
+    Obj {
+        mesh: String,
+        source: ObjError,
+    },
+    Gltf {
+        mesh: String,
+        source: GltfError,
+    },
+    UnknownMesh(String),
+}
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse(e) => write!(f, "could not parse scene: {}", e),
+            SceneError::Write(e) => write!(f, "could not write scene: {}", e),
+            SceneError::Obj { mesh, source } => write!(f, "mesh '{}': {}", mesh, source),
+            SceneError::Gltf { mesh, source } => write!(f, "mesh '{}': {}", mesh, source),
+            SceneError::UnknownMesh(mesh) => write!(f, "instance of unknown mesh '{}'", mesh),
+        }
+    }
+}
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse(e) => Some(e),
+            SceneError::Write(e) => Some(e),
+            SceneError::Obj { source, .. } => Some(source),
+            SceneError::Gltf { source, .. } => Some(source),
+            SceneError::UnknownMesh(_) => None,
+        }
+    }
+}
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        SceneError::Parse(e)
+    }
+}
+impl From<ron::Error> for SceneError {
+    fn from(e: ron::Error) -> Self {
+        SceneError::Write(e)
+    }
+}
+
+//the built-in shapes take the arguments of the Model constructors of the same name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) enum MeshSource {
+    Cube,
+    UvSphere {
+        segments: u32,
+        rings: u32,
+    },
+    Icosphere {
+        subdivisions: u32,
+    },
+    Cylinder {
+        segments: u32,
+    },
+    Cone {
+        segments: u32,
+    },
+    Capsule {
+        segments: u32,
+        rings: u32,
+        length: f32,
+    },
+    Grid {
+        subdivisions: u32,
+    },
+    Torus {
+        segments: u32,
+        rings: u32,
+        minor_radius: f32,
+    },
+    Arrow {
+        segments: u32,
+    },
+    //every group in the file
+    Obj(PathBuf),
+    //every primitive in the file, arranged the way the file's nodes arrange them
+    Gltf(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct CameraDescription {
+    pub(crate) position: [f32; 3],
+    pub(crate) view_direction: [f32; 3],
+    pub(crate) down_direction: [f32; 3],
+    //vertical field of view in degrees
+    pub(crate) fovy: f32,
+    pub(crate) near: f32,
+    pub(crate) far: f32,
+}
+impl Default for CameraDescription {
+    fn default() -> Self {
+        CameraDescription::of(&Camera::default())
+    }
+}
+impl CameraDescription {
+    fn of(camera: &Camera) -> CameraDescription {
+        CameraDescription {
+            position: camera.position(),
+            view_direction: camera.view_direction(),
+            down_direction: camera.down_direction(),
+            fovy: camera.fovy().to_degrees(),
+            near: camera.near(),
+            far: camera.far(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct InstanceDescription {
+    //a key of SceneDescription::meshes
+    pub(crate) mesh: String,
+    pub(crate) translation: [f32; 3],
+    //in degrees, applied about x first, then y, then z
+    pub(crate) rotation: [f32; 3],
+    pub(crate) scale: [f32; 3],
+    //None keeps the material colours of glTF meshes and is white for everything else
+    pub(crate) colour: Option<[f32; 3]>,
+}
+impl Default for InstanceDescription {
+    fn default() -> Self {
+        InstanceDescription {
+            mesh: String::new(),
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            scale: [1.0; 3],
+            colour: None,
+        }
+    }
+}
+impl InstanceDescription {
+    fn matrix(&self) -> na::Matrix4<f32> {
+        let [roll, pitch, yaw] = self.rotation.map(f32::to_radians);
+        na::Matrix4::new_translation(&self.translation.into())
+            * na::Rotation3::from_euler_angles(roll, pitch, yaw).to_homogeneous()
+            * na::Matrix4::new_nonuniform_scaling(&self.scale.into())
+    }
+    //the inverse of `matrix`, as far as the matrix has no shear
+    fn set_matrix(&mut self, matrix: &na::Matrix4<f32>) {
+        let mut linear: na::Matrix3<f32> = matrix.fixed_view::<3, 3>(0, 0).into();
+        let mut scale = na::Vector3::from_fn(|i, _| linear.column(i).norm());
+        //a mirroring is put into the scale, rotations cannot express it
+        if linear.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+        for i in 0..3 {
+            if scale[i] != 0.0 {
+                linear.column_mut(i).unscale_mut(scale[i]);
+            }
+        }
+        let (roll, pitch, yaw) = na::Rotation3::from_matrix(&linear).euler_angles();
+        self.translation = [matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]];
+        self.rotation = [roll, pitch, yaw].map(f32::to_degrees);
+        self.scale = scale.into();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct SceneDescription {
+    pub(crate) clear_colour: [f32; 4],
+    pub(crate) camera: CameraDescription,
+    pub(crate) meshes: BTreeMap<String, MeshSource>,
+    pub(crate) instances: Vec<InstanceDescription>,
+}
+impl Default for SceneDescription {
+    fn default() -> Self {
+        SceneDescription {
+            clear_colour: [0.0, 0.0, 0.08, 1.0],
+            camera: CameraDescription::default(),
+            meshes: BTreeMap::new(),
+            instances: vec![],
+        }
+    }
+}
+
+//one model instance that an InstanceDescription turned into
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Placement {
+    pub(crate) model: usize,
+    pub(crate) handle: InstanceHandle,
+    //where the mesh file puts the model, relative to the instance
+    local: na::Matrix4<f32>,
+    //the colour used if the description does not give one
+    default_colour: [f32; 3],
+}
+
+//one list per InstanceDescription, in the same order
+pub(crate) type Placements = Vec<Vec<Placement>>;
+
+pub(crate) struct BuiltScene {
+    pub(crate) models: Vec<Model<VertexData, InstanceData>>,
+    pub(crate) placements: Placements,
+    pub(crate) mesh_files: Vec<MeshFile>,
+    //statements the OBJ files had that their models were built without, by file
+    pub(crate) skipped: Vec<(PathBuf, Skipped)>,
+}
+
+impl SceneDescription {
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        SceneDescription::parse(&source)
+    }
+    pub(crate) fn parse(source: &str) -> Result<SceneDescription, SceneError> {
+        Ok(ron::from_str(source)?)
+    }
+    pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_ron()?).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+    pub(crate) fn to_ron(&self) -> Result<String, SceneError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+    //the aspect ratio still has to be set to that of the window
+    pub(crate) fn camera(&self) -> Camera {
+        let description = &self.camera;
+        Camera::new(
+            description.position,
+            description.view_direction,
+            description.down_direction,
+            description.fovy.to_radians(),
+            description.near,
+            description.far,
+        )
+    }
+    //mesh files are looked up relative to `directory`; the models still need their buffers
+    pub(crate) fn build(&self, directory: &Path) -> Result<BuiltScene, SceneError> {
+        let mut models = vec![];
+        let mut mesh_files = vec![];
+        let mut skipped = vec![];
+        //for every mesh: its models, each with the instances its file gives it
+        let mut parts: BTreeMap<&str, Vec<(usize, Vec<InstanceData>)>> = BTreeMap::new();
+        for (name, source) in &self.meshes {
+            let built = match source {
+                MeshSource::Gltf(path) => {
+                    Model::gltf_meshes(directory.join(path)).map_err(|source| SceneError::Gltf {
+                        mesh: name.clone(),
+                        source,
+                    })?
+                }
+                MeshSource::Obj(path) => {
+                    let (models, left_out) =
+                        Model::object(directory.join(path)).map_err(|source| SceneError::Obj {
+                            mesh: name.clone(),
+                            source,
+                        })?;
+                    let path = directory.join(path);
+                    skipped.extend(left_out.into_iter().map(|s| (path.clone(), s)));
+                    models
+                        .into_iter()
+                        .map(|model| (model, vec![unplaced()]))
+                        .collect()
+                }
+                shape => vec![(shape.build(), vec![unplaced()])],
+            };
+            let mesh_parts = parts.entry(name).or_default();
+            for (model, instances) in built {
+                mesh_parts.push((models.len(), instances));
+                models.push(model);
+            }
+            let file = match source {
+                MeshSource::Obj(path) => Some((path, MeshFormat::Obj)),
+                MeshSource::Gltf(path) => Some((path, MeshFormat::Gltf)),
+                _ => None,
+            };
+            if let Some((path, format)) = file {
+                mesh_files.push(MeshFile {
+                    path: directory.join(path),
+                    format,
+                    models: mesh_parts.iter().map(|(model, _)| *model).collect(),
+                });
+            }
+        }
+
+        let mut placements = vec![];
+        for instance in &self.instances {
+            let matrix = instance.matrix();
+            let mesh_parts = parts
+                .get(instance.mesh.as_str())
+                .ok_or_else(|| SceneError::UnknownMesh(instance.mesh.clone()))?;
+            let mut placed = vec![];
+            for (model, instances) in mesh_parts {
+                for part in instances {
+                    let local = na::Matrix4::from(part.modelmatrix);
+                    let handle = models[*model].insert_visibly(InstanceData {
+                        modelmatrix: (matrix * local).into(),
+                        colour: instance.colour.unwrap_or(part.colour),
+                    });
+                    placed.push(Placement {
+                        model: *model,
+                        handle,
+                        local,
+                        default_colour: part.colour,
+                    });
+                }
+            }
+            placements.push(placed);
+        }
+        Ok(BuiltScene {
+            models,
+            placements,
+            mesh_files,
+            skipped,
+        })
+    }
+    //takes over the camera, the clear colour and the transforms and colours of the instances
+    //placed by `build`; instances whose models were removed keep their description
+    pub(crate) fn capture(
+        &mut self,
+        models: &[Model<VertexData, InstanceData>],
+        placements: &[Vec<Placement>],
+        camera: &Camera,
+        clear_colour: [f32; 4],
+    ) {
+        self.clear_colour = clear_colour;
+        self.camera = CameraDescription::of(camera);
+        for (instance, placed) in self.instances.iter_mut().zip(placements) {
+            let current: Vec<(&Placement, &InstanceData)> = placed
+                .iter()
+                .filter_map(|placement| {
+                    let data = models.get(placement.model)?.get(placement.handle)?;
+                    Some((placement, data))
+                })
+                .collect();
+            let Some(&(first, data)) = current.first() else {
+                continue;
+            };
+            let local_inverse = first
+                .local
+                .try_inverse()
+                .unwrap_or_else(na::Matrix4::identity);
+            instance.set_matrix(&(na::Matrix4::from(data.modelmatrix) * local_inverse));
+            //a single new colour for all parts can be written down, a mix of them cannot
+            let unchanged = current.iter().all(|(placement, data)| {
+                data.colour == instance.colour.unwrap_or(placement.default_colour)
+            });
+            if !unchanged && current.iter().all(|(_, other)| other.colour == data.colour) {
+                instance.colour = Some(data.colour);
+            }
+        }
+    }
+}
+
+//a part of a mesh the file does not move or colour
+fn unplaced() -> InstanceData {
+    InstanceData {
+        modelmatrix: na::Matrix4::identity().into(),
+        colour: [1.0, 1.0, 1.0],
+    }
+}
+
+impl MeshSource {
+    //only for the built-in shapes
+    fn build(&self) -> Model<VertexData, InstanceData> {
+        match *self {
+            MeshSource::Cube => Model::cube(),
+            MeshSource::UvSphere { segments, rings } => Model::uv_sphere(segments, rings),
+            MeshSource::Icosphere { subdivisions } => Model::icosphere(subdivisions),
+            MeshSource::Cylinder { segments } => Model::cylinder(segments),
+            MeshSource::Cone { segments } => Model::cone(segments),
+            MeshSource::Capsule {
+                segments,
+                rings,
+                length,
+            } => Model::capsule(segments, rings, length),
+            MeshSource::Grid { subdivisions } => Model::grid(subdivisions),
+            MeshSource::Torus {
+                segments,
+                rings,
+                minor_radius,
+            } => Model::torus(segments, rings, minor_radius),
+            MeshSource::Arrow { segments } => Model::arrow(segments),
+            MeshSource::Obj(_) | MeshSource::Gltf(_) => unreachable!("not a built-in shape"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4)
+    }
+
+    #[test]
+    fn example_scene_builds() {
+        let description = SceneDescription::parse(include_str!("../scenes/example.ron")).unwrap();
+        let scene = description.build(Path::new("scenes")).unwrap();
+        assert_eq!(scene.models.len(), description.meshes.len());
+        assert_eq!(scene.placements.len(), description.instances.len());
+    }
+
+    #[test]
+    fn captured_scenes_survive_a_round_trip() {
+        let mut description = SceneDescription::parse(
+            r#"(
+                meshes: { "box": Cube, "ball": Icosphere(subdivisions: 1) },
+                instances: [
+                    (mesh: "box", translation: (1.0, 2.0, 3.0), rotation: (10.0, 20.0, 30.0),
+                     scale: (1.0, 2.0, 0.5)),
+                    (mesh: "ball", colour: Some((1.0, 0.0, 0.0))),
+                ],
+            )"#,
+        )
+        .unwrap();
+        let BuiltScene {
+            mut models,
+            placements,
+            ..
+        } = description.build(Path::new(".")).unwrap();
+        let original = description.clone();
+        let camera = description.camera();
+        description.capture(&models, &placements, &camera, description.clear_colour);
+        let box_ = &description.instances[0];
+        assert!(close(&box_.translation, &original.instances[0].translation));
+        assert!(close(&box_.rotation, &original.instances[0].rotation));
+        assert!(close(&box_.scale, &original.instances[0].scale));
+        assert_eq!(box_.colour, None);
+        assert!(close(
+            &description.camera.view_direction,
+            &original.camera.view_direction
+        ));
+
+        //recolouring an instance shows up in the saved file
+        let ball = placements[1][0];
+        models[ball.model].get_mut(ball.handle).unwrap().colour = [0.0, 1.0, 0.0];
+        description.capture(&models, &placements, &camera, [1.0; 4]);
+        let reloaded = SceneDescription::parse(&description.to_ron().unwrap()).unwrap();
+        assert_eq!(reloaded.instances[1].colour, Some([0.0, 1.0, 0.0]));
+        assert_eq!(reloaded.clear_colour, [1.0; 4]);
+    }
+
+    #[test]
+    fn unknown_meshes_are_reported() {
+        let description = SceneDescription::parse(r#"(instances: [(mesh: "missing")])"#).unwrap();
+        assert!(matches!(
+            description.build(Path::new(".")),
+            Err(SceneError::UnknownMesh(mesh)) if mesh == "missing"
+        ));
+    }
+}
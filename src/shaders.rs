@@ -0,0 +1,200 @@
+//GLSL compiled to SPIR-V while the program runs, so shaders can be edited without rebuilding; the
+//stage follows from the extension: .vert, .frag or .comp
+use crate::reflection::{Reflection, ReflectionError};
+use ash::vk;
+use std::path::{Path, PathBuf};
+
+//line is None for problems that do not belong to a place in the source
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CompileError {
+    pub(crate) line: Option<usize>,
+    pub(crate) message: String,
+}
+
+#[derive(Debug)]
+pub(crate) enum ShaderError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    UnknownStage(PathBuf),
+    Compile {
+        path: PathBuf,
+        errors: Vec<CompileError>,
+    },
+}
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ShaderError::UnknownStage(path) => write!(
+                f,
+                "{}: cannot tell the shader stage, use .vert, .frag or .comp",
+                path.display()
+            ),
+            //one line per error, like a compiler would print them
+            ShaderError::Compile { path, errors } => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    match error.line {
+                        Some(line) => write!(f, "{}:{}: {}", path.display(), line, error.message)?,
+                        None => write!(f, "{}: {}", path.display(), error.message)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Io { source, .. } => Some(source),
+            ShaderError::UnknownStage(_) | ShaderError::Compile { .. } => None,
+        }
+    }
+}
+
+//what the graphics pipeline is built from
+pub(crate) struct ShaderSet {
+    pub(crate) vertex: Vec<u32>,
+    pub(crate) fragment: Vec<u32>,
+}
+impl ShaderSet {
+    pub(crate) const VERTEX: &'static str = "shader.vert";
+    pub(crate) const FRAGMENT: &'static str = "shader.frag";
+
+    pub(crate) fn load(directory: &Path) -> Result<ShaderSet, ShaderError> {
+        Ok(ShaderSet {
+            vertex: compile(directory.join(Self::VERTEX))?,
+            fragment: compile(directory.join(Self::FRAGMENT))?,
+        })
+    }
+    pub(crate) fn reflect(&self) -> Result<Reflection, ReflectionError> {
+        Reflection::reflect(&[
+            (vk::ShaderStageFlags::VERTEX, &self.vertex),
+            (vk::ShaderStageFlags::FRAGMENT, &self.fragment),
+        ])
+    }
+}
+
+pub(crate) fn compile<P: AsRef<Path>>(path: P) -> Result<Vec<u32>, ShaderError> {
+    let path = path.as_ref();
+    let stage = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("vert") => naga::ShaderStage::Vertex,
+        Some("frag") => naga::ShaderStage::Fragment,
+        Some("comp") => naga::ShaderStage::Compute,
+        _ => return Err(ShaderError::UnknownStage(path.to_path_buf())),
+    };
+    let source = std::fs::read_to_string(path).map_err(|source| ShaderError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    compile_source(path, &source, stage)
+}
+
+//`path` only names the source in error messages
+pub(crate) fn compile_source(
+    path: &Path,
+    source: &str,
+    stage: naga::ShaderStage,
+) -> Result<Vec<u32>, ShaderError> {
+    let failed = |errors| ShaderError::Compile {
+        path: path.to_path_buf(),
+        errors,
+    };
+    let module = naga::front::glsl::Frontend::default()
+        .parse(&naga::front::glsl::Options::from(stage), source)
+        .map_err(|parse_errors| {
+            failed(
+                parse_errors
+                    .errors
+                    .iter()
+                    .map(|error| CompileError {
+                        line: Some(error.meta.location(source).line_number as usize),
+                        message: error.kind.to_string(),
+                    })
+                    .collect(),
+            )
+        })?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| {
+        let line = error
+            .spans()
+            .next()
+            .map(|(span, _)| span.location(source).line_number as usize);
+        let mut message = error.as_inner().to_string();
+        let mut cause = std::error::Error::source(error.as_inner());
+        while let Some(inner) = cause {
+            message += &format!(": {}", inner);
+            cause = inner.source();
+        }
+        failed(vec![CompileError { line, message }])
+    })?;
+    let options = naga::back::spv::Options {
+        //GLSL for Vulkan already uses Vulkan's clip space, there is nothing to flip
+        flags: naga::back::spv::WriterFlags::empty(),
+        ..Default::default()
+    };
+    naga::back::spv::write_vec(&module, &info, &options, None).map_err(|error| {
+        failed(vec![CompileError {
+            line: None,
+            message: error.to_string(),
+        }])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shaders() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders")
+    }
+
+    #[test]
+    fn shipped_shaders_compile() {
+        let set = ShaderSet::load(&shaders()).unwrap();
+        //the SPIR-V magic number
+        assert_eq!(set.vertex[0], 0x0723_0203);
+        assert_eq!(set.fragment[0], 0x0723_0203);
+    }
+
+    #[test]
+    fn errors_name_file_and_line() {
+        let source = "#version 450\nvoid main() {\n    float x = 1.0\n    x = 2.0;\n}\n";
+        let error = compile_source(
+            Path::new("broken.frag"),
+            source,
+            naga::ShaderStage::Fragment,
+        )
+        .unwrap_err();
+        let ShaderError::Compile { errors, .. } = &error else {
+            panic!("{}", error);
+        };
+        assert_eq!(errors[0].line, Some(4));
+        assert!(
+            error.to_string().starts_with("broken.frag:4: "),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn stages_come_from_the_extension() {
+        assert!(matches!(
+            compile("shader.glsl"),
+            Err(ShaderError::UnknownStage(_))
+        ));
+        assert!(matches!(
+            compile("does/not/exist.vert"),
+            Err(ShaderError::Io { .. })
+        ));
+    }
+}
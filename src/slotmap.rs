@@ -0,0 +1,362 @@
+//instances live densely packed with all visible ones first, so the visible range can be uploaded
+//as a single slice; handles point at slots that follow the instances around as they are swapped
+use std::ops::Range;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct InstanceHandle {
+    index: u32,
+    //bumped every time the slot is freed, so handles to removed instances stop matching
+    generation: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct InvalidHandle;
+impl std::fmt::Display for InvalidHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid handle")
+    }
+}
+impl std::error::Error for InvalidHandle {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+struct Slot {
+    generation: u32,
+    //position in `values`, None while the slot is free
+    dense_index: Option<usize>,
+}
+
+pub(crate) struct DenseSlotMap<T> {
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+    values: Vec<T>,
+    //slot index of every entry in `values`
+    slot_indices: Vec<u32>,
+    first_invisible: usize,
+    //positions in `values` written since the last take_dirty_ranges, possibly more than once
+    dirty: Vec<usize>,
+    //set instead of letting `dirty` grow beyond the amount of instances
+    all_dirty: bool,
+}
+
+impl<T> Default for DenseSlotMap<T> {
+    fn default() -> Self {
+        DenseSlotMap {
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            values: Vec::new(),
+            slot_indices: Vec::new(),
+            first_invisible: 0,
+            dirty: Vec::new(),
+            all_dirty: false,
+        }
+    }
+}
+
+impl<T> DenseSlotMap<T> {
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub(crate) fn visible(&self) -> &[T] {
+        &self.values[0..self.first_invisible]
+    }
+    pub(crate) fn amount_visible(&self) -> usize {
+        self.first_invisible
+    }
+    fn dense_index(&self, handle: InstanceHandle) -> Option<usize> {
+        let slot = self.slots.get(handle.index as usize)?;
+        if slot.generation == handle.generation {
+            slot.dense_index
+        } else {
+            None
+        }
+    }
+    pub(crate) fn get(&self, handle: InstanceHandle) -> Option<&T> {
+        self.dense_index(handle).map(|index| &self.values[index])
+    }
+    //counts as a change, whether or not the instance is written to
+    pub(crate) fn get_mut(&mut self, handle: InstanceHandle) -> Option<&mut T> {
+        let index = self.dense_index(handle)?;
+        self.mark_dirty(index);
+        Some(&mut self.values[index])
+    }
+    pub(crate) fn is_visible(&self, handle: InstanceHandle) -> Result<bool, InvalidHandle> {
+        self.dense_index(handle)
+            .map(|index| index < self.first_invisible)
+            .ok_or(InvalidHandle)
+    }
+    //new instances start out invisible
+    pub(crate) fn insert(&mut self, value: T) -> InstanceHandle {
+        let dense_index = self.values.len();
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.slots[index as usize].dense_index = Some(dense_index);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    dense_index: Some(dense_index),
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.values.push(value);
+        self.slot_indices.push(index);
+        InstanceHandle {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+    pub(crate) fn make_visible(&mut self, handle: InstanceHandle) -> Result<(), InvalidHandle> {
+        let index = self.dense_index(handle).ok_or(InvalidHandle)?;
+        //if already visible: do nothing
+        if index >= self.first_invisible {
+            //else: move to position first_invisible and increase value of first_invisible
+            self.swap_dense(index, self.first_invisible);
+            self.first_invisible += 1;
+        }
+        Ok(())
+    }
+    pub(crate) fn make_invisible(&mut self, handle: InstanceHandle) -> Result<(), InvalidHandle> {
+        let index = self.dense_index(handle).ok_or(InvalidHandle)?;
+        //if already invisible: do nothing
+        if index < self.first_invisible {
+            //else: move to position before first_invisible and decrease value of first_invisible
+            self.swap_dense(index, self.first_invisible - 1);
+            self.first_invisible -= 1;
+        }
+        Ok(())
+    }
+    pub(crate) fn remove(&mut self, handle: InstanceHandle) -> Result<T, InvalidHandle> {
+        self.make_invisible(handle)?;
+        //invisible now, so moving it to the very end keeps the visible range packed
+        let index = self.dense_index(handle).ok_or(InvalidHandle)?;
+        let last = self.values.len() - 1;
+        self.swap_dense(index, last);
+        self.slot_indices.pop();
+        let slot = &mut self.slots[handle.index as usize];
+        slot.dense_index = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(handle.index);
+        //must be Some(), otherwise we couldn't have found an index
+        Ok(self.values.pop().unwrap())
+    }
+    fn mark_dirty(&mut self, index: usize) {
+        if self.all_dirty {
+            return;
+        }
+        if self.dirty.len() >= self.values.len() {
+            self.all_dirty = true;
+            self.dirty.clear();
+        } else {
+            self.dirty.push(index);
+        }
+    }
+    //the visible positions changed since the last call, in order and with neighbours merged
+    pub(crate) fn take_dirty_ranges(&mut self) -> Vec<Range<usize>> {
+        if std::mem::take(&mut self.all_dirty) {
+            self.dirty.extend(0..self.first_invisible);
+        }
+        merged_ranges(&mut self.dirty, self.first_invisible)
+    }
+    //for when the uploaded copy was lost, such as after a failed upload
+    pub(crate) fn mark_all_dirty(&mut self) {
+        self.all_dirty = true;
+        self.dirty.clear();
+    }
+    //both positions get new contents, even when they are the same one: making the first invisible
+    //instance visible swaps it with itself
+    fn swap_dense(&mut self, index1: usize, index2: usize) {
+        self.mark_dirty(index1);
+        self.mark_dirty(index2);
+        if index1 == index2 {
+            return;
+        }
+        self.values.swap(index1, index2);
+        self.slot_indices.swap(index1, index2);
+        self.slots[self.slot_indices[index1] as usize].dense_index = Some(index1);
+        self.slots[self.slot_indices[index2] as usize].dense_index = Some(index2);
+    }
+}
+
+//drains `indices` into sorted ranges of consecutive indices below `limit`
+fn merged_ranges(indices: &mut Vec<usize>, limit: usize) -> Vec<Range<usize>> {
+    indices.sort_unstable();
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for index in indices.drain(..).take_while(|&index| index < limit) {
+        match ranges.last_mut() {
+            Some(last) if index <= last.end => last.end = index + 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+//sorts `ranges` and joins those that overlap or touch
+pub(crate) fn merge_ranges(ranges: &mut Vec<Range<usize>>) {
+    ranges.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges.drain(..).filter(|range| !range.is_empty()) {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    *ranges = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Insert(u32),
+        InsertVisibly(u32),
+        //indices into the list of every handle handed out so far, including removed ones
+        Remove(usize),
+        MakeVisible(usize),
+        MakeInvisible(usize),
+        Set(usize, u32),
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            any::<u32>().prop_map(Operation::Insert),
+            any::<u32>().prop_map(Operation::InsertVisibly),
+            any::<usize>().prop_map(Operation::Remove),
+            any::<usize>().prop_map(Operation::MakeVisible),
+            any::<usize>().prop_map(Operation::MakeInvisible),
+            (any::<usize>(), any::<u32>()).prop_map(|(h, v)| Operation::Set(h, v)),
+        ]
+    }
+
+    proptest! {
+        //replays random operations against a plain list of (handle, value, visible, alive)
+        #[test]
+        fn behaves_like_a_list_of_instances(operations in prop::collection::vec(operation(), 1..200)) {
+            let mut map = DenseSlotMap::default();
+            let mut expected: Vec<(InstanceHandle, u32, bool, bool)> = vec![];
+            //what an instance buffer updated with only the dirty ranges would hold
+            let mut uploaded: Vec<Option<u32>> = vec![];
+            for operation in operations {
+                let amount = expected.len().max(1);
+                let pick = |i: usize| i % amount;
+                match operation {
+                    Operation::Insert(value) => {
+                        expected.push((map.insert(value), value, false, true));
+                    }
+                    Operation::InsertVisibly(value) => {
+                        let handle = map.insert(value);
+                        map.make_visible(handle).unwrap();
+                        expected.push((handle, value, true, true));
+                    }
+                    Operation::Remove(i) if !expected.is_empty() => {
+                        let (handle, value, _, alive) = &mut expected[pick(i)];
+                        if *alive {
+                            prop_assert_eq!(map.remove(*handle), Ok(*value));
+                            *alive = false;
+                        } else {
+                            prop_assert_eq!(map.remove(*handle), Err(InvalidHandle));
+                        }
+                    }
+                    Operation::MakeVisible(i) if !expected.is_empty() => {
+                        let (handle, _, visible, alive) = &mut expected[pick(i)];
+                        prop_assert_eq!(map.make_visible(*handle).is_ok(), *alive);
+                        *visible |= *alive;
+                    }
+                    Operation::MakeInvisible(i) if !expected.is_empty() => {
+                        let (handle, _, visible, alive) = &mut expected[pick(i)];
+                        prop_assert_eq!(map.make_invisible(*handle).is_ok(), *alive);
+                        *visible = false;
+                    }
+                    Operation::Set(i, new_value) if !expected.is_empty() => {
+                        let (handle, value, _, alive) = &mut expected[pick(i)];
+                        match map.get_mut(*handle) {
+                            Some(stored) => {
+                                prop_assert!(*alive);
+                                *stored = new_value;
+                                *value = new_value;
+                            }
+                            None => prop_assert!(!*alive),
+                        }
+                    }
+                    _ => {}
+                }
+                //every handle still resolves to its own value, stale ones to nothing
+                for (handle, value, visible, alive) in &expected {
+                    if *alive {
+                        prop_assert_eq!(map.get(*handle), Some(value));
+                        prop_assert_eq!(map.is_visible(*handle), Ok(*visible));
+                    } else {
+                        prop_assert_eq!(map.get(*handle), None);
+                        prop_assert_eq!(map.is_visible(*handle), Err(InvalidHandle));
+                    }
+                }
+                //and the visible ones are exactly the packed front of the storage
+                let mut visible: Vec<u32> = expected
+                    .iter()
+                    .filter(|(_, _, visible, alive)| *visible && *alive)
+                    .map(|(_, value, _, _)| *value)
+                    .collect();
+                let mut packed = map.visible().to_vec();
+                visible.sort_unstable();
+                packed.sort_unstable();
+                prop_assert_eq!(packed, visible);
+                prop_assert_eq!(map.len(), expected.iter().filter(|e| e.3).count());
+                uploaded.resize(map.amount_visible(), None);
+                for range in map.take_dirty_ranges() {
+                    for i in range {
+                        uploaded[i] = Some(map.visible()[i]);
+                    }
+                }
+                let current: Vec<Option<u32>> = map.visible().iter().copied().map(Some).collect();
+                prop_assert_eq!(&uploaded, &current);
+            }
+        }
+    }
+
+    #[test]
+    fn only_changed_instances_are_dirty() {
+        let mut map = DenseSlotMap::default();
+        let handles: Vec<_> = (0..6)
+            .map(|i| {
+                let handle = map.insert(i);
+                map.make_visible(handle).unwrap();
+                handle
+            })
+            .collect();
+        assert_eq!(map.take_dirty_ranges(), vec![0..6]);
+        assert_eq!(map.take_dirty_ranges(), vec![]);
+        *map.get_mut(handles[4]).unwrap() = 40;
+        *map.get_mut(handles[1]).unwrap() = 10;
+        *map.get_mut(handles[2]).unwrap() = 20;
+        assert_eq!(map.take_dirty_ranges(), vec![1..3, 4..5]);
+        //the last visible instance moves into the gap, the hidden one needs no upload
+        map.make_invisible(handles[0]).unwrap();
+        assert_eq!(map.take_dirty_ranges(), vec![0..1]);
+    }
+
+    #[test]
+    fn ranges_are_merged_in_order() {
+        let mut ranges = vec![6..8, 0..2, 1..3, 3..4, 5..5, 7..9];
+        merge_ranges(&mut ranges);
+        assert_eq!(ranges, vec![0..4, 6..9]);
+    }
+
+    #[test]
+    fn reused_slots_do_not_revive_old_handles() {
+        let mut map = DenseSlotMap::default();
+        let old = map.insert(1);
+        map.remove(old).unwrap();
+        let new = map.insert(2);
+        assert_eq!(old.index, new.index);
+        assert_eq!(map.get(old), None);
+        assert_eq!(map.get(new), Some(&2));
+    }
+}
@@ -37,6 +37,19 @@ impl Surface {
                 .get_physical_device_surface_capabilities(physical_device, self.surface)
         }
     }
+    pub(crate) fn get_present_support(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        queuefamily_index: u32,
+    ) -> Result<bool, vk::Result> {
+        unsafe {
+            self.surface_loader.get_physical_device_surface_support(
+                physical_device,
+                queuefamily_index,
+                self.surface,
+            )
+        }
+    }
     fn get_present_modes(
         &self,
         physical_device: vk::PhysicalDevice,
@@ -14,14 +14,12 @@ pub(crate) struct Swapchain {
     pub(crate) framebuffers: Vec<vk::Framebuffer>,
     pub(crate) surface_format: vk::SurfaceFormatKHR,
     pub(crate) extent: vk::Extent2D,
-    pub(crate) image_available: Vec<vk::Semaphore>,
-    pub(crate) rendering_finished: Vec<vk::Semaphore>,
-    pub(crate) may_begin_drawing: Vec<vk::Fence>,
-    pub(crate) amount_of_images: u32,
-    pub(crate) current_image: usize,
+    //fence of the frame that last drew into each image, null if none has yet
+    pub(crate) images_in_flight: Vec<vk::Fence>,
 }
 
 impl Swapchain {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn init(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
@@ -29,9 +27,25 @@ impl Swapchain {
         surfaces: &Surface,
         queue_families: &QueueFamilies,
         allocator: &vk_mem::Allocator,
+        window_extent: vk::Extent2D,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<Swapchain, vk::Result> {
         let surface_capabilities = surfaces.get_capabilities(physical_device)?;
-        let extent = surface_capabilities.current_extent;
+        //u32::MAX means the surface lets us pick, so follow the window within the allowed range
+        let extent = if surface_capabilities.current_extent.width != u32::MAX {
+            surface_capabilities.current_extent
+        } else {
+            vk::Extent2D {
+                width: window_extent.width.clamp(
+                    surface_capabilities.min_image_extent.width,
+                    surface_capabilities.max_image_extent.width,
+                ),
+                height: window_extent.height.clamp(
+                    surface_capabilities.min_image_extent.height,
+                    surface_capabilities.max_image_extent.height,
+                ),
+            }
+        };
         //let surface_present_modes = surfaces.get_present_modes(physical_device)?;
         let surface_formats = *surfaces.get_formats(physical_device)?.last().unwrap(); //first was giving warnings
         let queuefamilies = [queue_families.graphics_q_index.unwrap()];
@@ -46,14 +60,15 @@ impl Swapchain {
             .min_image_count(desired_image_count)
             .image_format(surface_formats.format)
             .image_color_space(surface_formats.color_space)
-            .image_extent(surface_capabilities.current_extent)
+            .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&queuefamilies)
             .pre_transform(surface_capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO);
+            .present_mode(vk::PresentModeKHR::FIFO)
+            .old_swapchain(old_swapchain);
         let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, logical_device);
         let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
         let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
@@ -109,33 +124,8 @@ impl Swapchain {
             .subresource_range(*subresource_range);
         let depth_imageview =
             unsafe { logical_device.create_image_view(&imageview_create_info, None) }?;
-        let mut image_available = vec![];
-        let mut rendering_finished = vec![];
-        let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
-        for _ in 0..desired_image_count {
-            let semaphore_available =
-                unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
-            let semaphore_finished =
-                unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
-            image_available.push(semaphore_available);
-            rendering_finished.push(semaphore_finished);
-        }
-        let mut image_available = vec![];
-        let mut rendering_finished = vec![];
-        let mut may_begin_drawing = vec![];
-        let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
-        let fenceinfo = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
-        for _ in 0..desired_image_count {
-            let semaphore_available =
-                unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
-            let semaphore_finished =
-                unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
-            image_available.push(semaphore_available);
-            rendering_finished.push(semaphore_finished);
-            let fence = unsafe { logical_device.create_fence(&fenceinfo, None) }?;
-            may_begin_drawing.push(fence);
-        }
-
+        //the driver may hand out more images than we asked for
+        let images_in_flight = vec![vk::Fence::null(); swapchain_images.len()];
         Ok(Swapchain {
             swapchain_loader,
             swapchain,
@@ -147,11 +137,7 @@ impl Swapchain {
             framebuffers: vec![],
             surface_format: surface_formats,
             extent,
-            amount_of_images: desired_image_count,
-            image_available,
-            rendering_finished,
-            current_image: 0,
-            may_begin_drawing,
+            images_in_flight,
         })
     }
     pub(crate) fn create_framebuffers(
@@ -174,19 +160,11 @@ impl Swapchain {
     }
     pub(crate) unsafe fn cleanup(
         &mut self,
-        logical_device: &ash::Device, /*allocator: &vk_mem::Allocator*/
+        logical_device: &ash::Device,
+        allocator: &vk_mem::Allocator,
     ) {
         logical_device.destroy_image_view(self.depth_imageview, None);
-        //allocator.destroy_image(self.depth_image, &self.depth_image_allocation);
-        for fence in &self.may_begin_drawing {
-            logical_device.destroy_fence(*fence, None);
-        }
-        for semaphore in &self.image_available {
-            logical_device.destroy_semaphore(*semaphore, None);
-        }
-        for semaphore in &self.rendering_finished {
-            logical_device.destroy_semaphore(*semaphore, None);
-        }
+        allocator.destroy_image(self.depth_image, &mut self.depth_image_allocation);
         for fb in &self.framebuffers {
             logical_device.destroy_framebuffer(*fb, None);
         }
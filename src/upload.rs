@@ -0,0 +1,286 @@
+//device-local buffers, which the CPU cannot write, filled by copying from staging buffers on the
+//transfer queue; the graphics queue waits for the copies and takes the buffers over from there
+use crate::buffer::{Buffer, BufferError, TypedBuffer};
+use crate::commandbuffers::Pools;
+use ash::vk;
+use bytemuck::Pod;
+
+//copies recorded since the last submit
+struct Batch {
+    commandbuffer: vk::CommandBuffer,
+    staging: Vec<Buffer>,
+    acquires: Vec<vk::BufferMemoryBarrier>,
+}
+
+//copies the transfer queue may still be working on; their staging buffers are freed once the
+//fence has signalled
+struct InFlight {
+    commandbuffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    staging: Vec<Buffer>,
+}
+
+//what a graphics submission has to do before it may use the uploaded buffers
+pub(crate) struct Submission {
+    //to be waited on by the submission, which then owns it until its fence has signalled
+    pub(crate) finished: vk::Semaphore,
+    //empty when transfer and graphics share a queue family
+    acquires: Vec<vk::BufferMemoryBarrier>,
+}
+impl Submission {
+    //has to come before anything in `commandbuffer` reads the buffers
+    pub(crate) unsafe fn record_acquires(
+        &self,
+        logical_device: &ash::Device,
+        commandbuffer: vk::CommandBuffer,
+    ) {
+        if self.acquires.is_empty() {
+            return;
+        }
+        //the semaphore already orders the copies before this, so there is nothing to wait for
+        logical_device.cmd_pipeline_barrier(
+            commandbuffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::DependencyFlags::empty(),
+            &[],
+            &self.acquires,
+            &[],
+        );
+    }
+}
+
+pub(crate) struct Uploader {
+    device: ash::Device,
+    queue: vk::Queue,
+    //the pool of the transfer family, owned by Pools
+    commandpool: vk::CommandPool,
+    transfer_family: u32,
+    graphics_family: u32,
+    batch: Option<Batch>,
+    in_flight: Vec<InFlight>,
+    //finished ones, kept for the next uploads
+    commandbuffers: Vec<vk::CommandBuffer>,
+    fences: Vec<vk::Fence>,
+    semaphores: Vec<vk::Semaphore>,
+}
+
+impl Uploader {
+    pub(crate) fn init(
+        logical_device: &ash::Device,
+        pools: &Pools,
+        transfer_queue: vk::Queue,
+        transfer_family: u32,
+        graphics_family: u32,
+    ) -> Uploader {
+        Uploader {
+            device: logical_device.clone(),
+            queue: transfer_queue,
+            commandpool: pools.commandpool_transfer,
+            transfer_family,
+            graphics_family,
+            batch: None,
+            in_flight: vec![],
+            commandbuffers: vec![],
+            fences: vec![],
+            semaphores: vec![],
+        }
+    }
+    //a device-local buffer that will hold `data` once the next submission has finished; it must not
+    //be used by a graphics submission that does not wait for that
+    pub(crate) fn upload<T: Pod>(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Buffer, BufferError> {
+        let bytes = std::mem::size_of_val(data) as u64;
+        let mut staging = TypedBuffer::new(
+            allocator,
+            data.len(),
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk_mem::MemoryUsage::CpuOnly,
+        )?;
+        let destination = staging.write(allocator, 0, data).and_then(|()| {
+            Buffer::new(
+                allocator,
+                bytes,
+                usage | vk::BufferUsageFlags::TRANSFER_DST,
+                vk_mem::MemoryUsage::GpuOnly,
+            )
+            .map_err(BufferError::from)
+        });
+        let mut destination = match destination {
+            Ok(destination) => destination,
+            Err(e) => {
+                unsafe { staging.destroy(allocator) };
+                return Err(e);
+            }
+        };
+        let commandbuffer = match self.recording() {
+            Ok(commandbuffer) => commandbuffer,
+            Err(e) => {
+                unsafe {
+                    staging.destroy(allocator);
+                    destination.destroy(allocator);
+                }
+                return Err(e.into());
+            }
+        };
+        unsafe {
+            self.device.cmd_copy_buffer(
+                commandbuffer,
+                staging.buffer(),
+                destination.buffer,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size: bytes,
+                }],
+            );
+        }
+        let batch = self.batch.as_mut().unwrap();
+        batch.staging.push(staging.into());
+        //exclusive buffers are released by the transfer family and acquired by the graphics one,
+        //with the same barrier recorded on both queues
+        if self.transfer_family != self.graphics_family {
+            let ownership_transfer = vk::BufferMemoryBarrier::builder()
+                .src_queue_family_index(self.transfer_family)
+                .dst_queue_family_index(self.graphics_family)
+                .buffer(destination.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE);
+            let release = ownership_transfer
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .build();
+            unsafe {
+                self.device.cmd_pipeline_barrier(
+                    commandbuffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[release],
+                    &[],
+                );
+            }
+            batch.acquires.push(vk::BufferMemoryBarrier {
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::INDEX_READ,
+                ..release
+            });
+        }
+        Ok(destination)
+    }
+    //the command buffer of the current batch, begun if there is none yet
+    fn recording(&mut self) -> Result<vk::CommandBuffer, vk::Result> {
+        if let Some(batch) = &self.batch {
+            return Ok(batch.commandbuffer);
+        }
+        let commandbuffer = match self.commandbuffers.pop() {
+            Some(commandbuffer) => commandbuffer,
+            None => {
+                let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(self.commandpool)
+                    .command_buffer_count(1);
+                let commandbuffers =
+                    unsafe { self.device.allocate_command_buffers(&allocate_info)? };
+                commandbuffers[0]
+            }
+        };
+        let begininfo = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        if let Err(e) = unsafe { self.device.begin_command_buffer(commandbuffer, &begininfo) } {
+            self.commandbuffers.push(commandbuffer);
+            return Err(e);
+        }
+        self.batch = Some(Batch {
+            commandbuffer,
+            staging: vec![],
+            acquires: vec![],
+        });
+        Ok(commandbuffer)
+    }
+    //sends the copies recorded since the last call to the transfer queue, None if there were none
+    pub(crate) fn submit(&mut self) -> Result<Option<Submission>, vk::Result> {
+        let Some(batch) = self.batch.take() else {
+            return Ok(None);
+        };
+        let semaphore = match self.semaphores.pop() {
+            Some(semaphore) => semaphore,
+            None => unsafe {
+                self.device
+                    .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)?
+            },
+        };
+        let fence = match self.fences.pop() {
+            Some(fence) => fence,
+            None => unsafe {
+                self.device
+                    .create_fence(&vk::FenceCreateInfo::builder(), None)?
+            },
+        };
+        let commandbuffers = [batch.commandbuffer];
+        let semaphores = [semaphore];
+        let submit_info = [vk::SubmitInfo::builder()
+            .command_buffers(&commandbuffers)
+            .signal_semaphores(&semaphores)
+            .build()];
+        unsafe {
+            self.device.end_command_buffer(batch.commandbuffer)?;
+            self.device.queue_submit(self.queue, &submit_info, fence)?;
+        }
+        self.in_flight.push(InFlight {
+            commandbuffer: batch.commandbuffer,
+            fence,
+            staging: batch.staging,
+        });
+        Ok(Some(Submission {
+            finished: semaphore,
+            acquires: batch.acquires,
+        }))
+    }
+    //frees the staging buffers of finished copies, without waiting for the others
+    pub(crate) fn collect(&mut self, allocator: &vk_mem::Allocator) -> Result<(), vk::Result> {
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            if !unsafe { self.device.get_fence_status(self.in_flight[i].fence)? } {
+                i += 1;
+                continue;
+            }
+            let finished = self.in_flight.swap_remove(i);
+            for mut buffer in finished.staging {
+                unsafe { buffer.destroy(allocator) };
+            }
+            unsafe { self.device.reset_fences(&[finished.fence])? };
+            self.fences.push(finished.fence);
+            self.commandbuffers.push(finished.commandbuffer);
+        }
+        Ok(())
+    }
+    //only once the submission that waited on it has finished
+    pub(crate) fn recycle(&mut self, semaphore: vk::Semaphore) {
+        self.semaphores.push(semaphore);
+    }
+    //the command buffers go away with their pool; the device must be idle
+    pub(crate) unsafe fn cleanup(&mut self, allocator: &vk_mem::Allocator) {
+        for in_flight in self.in_flight.drain(..) {
+            self.fences.push(in_flight.fence);
+            for mut buffer in in_flight.staging {
+                buffer.destroy(allocator);
+            }
+        }
+        if let Some(batch) = self.batch.take() {
+            for mut buffer in batch.staging {
+                buffer.destroy(allocator);
+            }
+        }
+        for fence in self.fences.drain(..) {
+            self.device.destroy_fence(fence, None);
+        }
+        for semaphore in self.semaphores.drain(..) {
+            self.device.destroy_semaphore(semaphore, None);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//how vertex and instance structs are laid out in their buffers, so the pipeline can be built for any
+//Model<V, I>; derive VertexLayout on a #[repr(C)] struct whose fields implement AttributeType
+use ash::vk;
+pub(crate) use vertexlayout_derive::VertexLayout;
+
+//one field of a vertex or instance struct, taking up one location in the vertex shader
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct VertexAttribute {
+    pub(crate) offset: u32,
+    pub(crate) format: vk::Format,
+}
+
+//field types the vertex shader can read; offsets are relative to the field
+pub(crate) trait AttributeType {
+    const ATTRIBUTES: &'static [VertexAttribute];
+}
+
+macro_rules! attribute_types {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(impl AttributeType for $ty {
+            const ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute {
+                offset: 0,
+                format: vk::Format::$format,
+            }];
+        })*
+    };
+}
+attribute_types! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+}
+
+//matrices take up one location per column
+impl AttributeType for [[f32; 3]; 3] {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            offset: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+        },
+        VertexAttribute {
+            offset: 12,
+            format: vk::Format::R32G32B32_SFLOAT,
+        },
+        VertexAttribute {
+            offset: 24,
+            format: vk::Format::R32G32B32_SFLOAT,
+        },
+    ];
+}
+impl AttributeType for [[f32; 4]; 4] {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            offset: 0,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+        },
+        VertexAttribute {
+            offset: 16,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+        },
+        VertexAttribute {
+            offset: 32,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+        },
+        VertexAttribute {
+            offset: 48,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+        },
+    ];
+}
+
+pub(crate) trait VertexLayout: Copy {
+    //in the order of the shader locations
+    fn attributes() -> Vec<VertexAttribute>;
+    fn stride() -> u32 {
+        std::mem::size_of::<Self>() as u32
+    }
+}
+
+//what the pipeline needs of one vertex buffer binding
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BufferLayout {
+    pub(crate) stride: u32,
+    pub(crate) attributes: Vec<VertexAttribute>,
+}
+impl BufferLayout {
+    pub(crate) fn of<T: VertexLayout>() -> BufferLayout {
+        BufferLayout {
+            stride: T::stride(),
+            attributes: T::attributes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, VertexLayout)]
+    #[repr(C)]
+    struct Textured {
+        position: [f32; 3],
+        uv: [f32; 2],
+        material: u32,
+        transform: [[f32; 3]; 3],
+    }
+
+    #[test]
+    fn derived_layouts_follow_the_fields() {
+        let layout = BufferLayout::of::<Textured>();
+        assert_eq!(layout.stride, 60);
+        let attributes: Vec<(u32, vk::Format)> = layout
+            .attributes
+            .iter()
+            .map(|attribute| (attribute.offset, attribute.format))
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                (0, vk::Format::R32G32B32_SFLOAT),
+                (12, vk::Format::R32G32_SFLOAT),
+                (20, vk::Format::R32_UINT),
+                (24, vk::Format::R32G32B32_SFLOAT),
+                (36, vk::Format::R32G32B32_SFLOAT),
+                (48, vk::Format::R32G32B32_SFLOAT),
+            ]
+        );
+    }
+}
@@ -1,119 +1,246 @@
-use crate::buffer::Buffer;
-use crate::commandbuffers::{create_commandbuffers, Pools};
+use crate::buffer::BufferError;
+use crate::camera::Camera;
+use crate::commandbuffers::Pools;
+use crate::culling::Frustum;
 use crate::debug::Debug;
+use crate::frames::{init_descriptor_pool, Frame};
+use crate::gpuculling;
 use crate::initialization::{
-    get_physical_device_and_properties, init_device_and_queues, init_instance, QueueFamilies,
-    Queues,
+    get_physical_device_and_properties, init_device_and_queues, init_instance, DeviceChoice,
+    QueueFamilies, Queues,
 };
-use crate::model::{InstanceData, Model};
-use crate::rendering::{init_renderpass, Pipeline};
+use crate::model::{InstanceData, Model, VertexData};
+use crate::modelgroup::{AnyModelGroup, ModelGroup};
+use crate::offscreen::Offscreen;
+use crate::pipelinecache::{self, PipelineCache};
+use crate::rendering::{init_renderpass, ComputePipeline, Pipeline, PipelineError, PipelineState};
+use crate::shaders::ShaderSet;
 use crate::surface::Surface;
 use crate::swapchain::Swapchain;
+use crate::upload::Uploader;
+use crate::vertexlayout::VertexLayout;
 use ash::{vk, Entry};
-use nalgebra as na;
+use bytemuck::Pod;
+
+pub(crate) struct RendererConfig {
+    //forces a GPU, otherwise the GRAPHICS_DEVICE environment variable is consulted
+    pub(crate) device_choice: Option<DeviceChoice>,
+    //how many frames the CPU may record ahead of the GPU; headless rendering always uses one
+    pub(crate) frames_in_flight: usize,
+    //where shader.vert and shader.frag are compiled from
+    pub(crate) shader_directory: std::path::PathBuf,
+    //loaded at start up and saved when the VkInterface is dropped, None to not keep one
+    pub(crate) pipeline_cache: Option<std::path::PathBuf>,
+    //culls the instances of `models` in a compute shader and draws them indirectly, for scenes
+    //with too many instances to go through on the CPU every frame
+    pub(crate) gpu_culling: bool,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            device_choice: None,
+            frames_in_flight: 2,
+            shader_directory: "shaders".into(),
+            pipeline_cache: pipelinecache::default_path(),
+            gpu_culling: false,
+        }
+    }
+}
 
 pub(crate) struct VkInterface {
-    pub(crate) window: winit::window::Window,
+    //window, surface and swapchain are None in headless mode, where offscreen is used instead
+    pub(crate) window: Option<winit::window::Window>,
     entry: Entry,
     instance: ash::Instance,
     debug: std::mem::ManuallyDrop<Debug>,
-    surface: std::mem::ManuallyDrop<Surface>,
+    surface: Option<Surface>,
     physical_device: vk::PhysicalDevice,
     physical_device_properties: vk::PhysicalDeviceProperties,
     queue_families: QueueFamilies,
     pub(crate) device: ash::Device,
     pub(crate) queues: Queues,
-    pub(crate) swapchain: Swapchain,
+    pub(crate) swapchain: Option<Swapchain>,
+    pub(crate) offscreen: Option<Offscreen>,
     renderpass: vk::RenderPass,
-    pipeline: Pipeline,
+    pipeline_cache: PipelineCache,
+    //keyed by the model group drawn with them, None for `models`, and their state; the first one
+    //is built from the main shaders at start up, the others share its layouts
+    pipelines: Vec<(Option<usize>, Pipeline)>,
+    //from culling/cull.comp, when gpu_culling was asked for and the graphics queue can run it
+    culling_pipeline: Option<ComputePipeline>,
+    //not tried again until the shaders are reloaded
+    failed_pipelines: Vec<(Option<usize>, PipelineState)>,
+    enabled_features: vk::PhysicalDeviceFeatures,
     pools: Pools,
-    pub(crate) command_buffers: Vec<vk::CommandBuffer>,
+    //vertex and index data goes through it to device-local memory
+    pub(crate) uploader: Uploader,
     pub(crate) allocator: std::mem::ManuallyDrop<vk_mem::Allocator>,
-    pub(crate) models: Vec<Model<[f32; 3], InstanceData>>,
-    pub(crate) uniformbuffer: Buffer,
+    pub(crate) models: Vec<Model<VertexData, InstanceData>>,
+    model_groups: Vec<Box<dyn AnyModelGroup>>,
     descriptor_pool: vk::DescriptorPool,
-    descriptor_sets: Vec<vk::DescriptorSet>,
+    pub(crate) frames: Vec<Frame>,
+    pub(crate) current_frame: usize,
+    pub(crate) clear_colour: [f32; 4],
+    shader_directory: std::path::PathBuf,
 }
 
 impl VkInterface {
     pub(crate) fn init(
         window: winit::window::Window,
+        config: RendererConfig,
     ) -> Result<VkInterface, Box<dyn std::error::Error>> {
+        let window_size = window.inner_size();
+        Self::init_with_target(
+            Some(window),
+            vk::Extent2D {
+                width: window_size.width,
+                height: window_size.height,
+            },
+            config,
+        )
+    }
+    //renders into an offscreen colour and depth target, for machines without a display
+    pub(crate) fn init_headless(
+        width: u32,
+        height: u32,
+        config: RendererConfig,
+    ) -> Result<VkInterface, Box<dyn std::error::Error>> {
+        let config = RendererConfig {
+            frames_in_flight: 1,
+            ..config
+        };
+        Self::init_with_target(None, vk::Extent2D { width, height }, config)
+    }
+    fn init_with_target(
+        window: Option<winit::window::Window>,
+        extent: vk::Extent2D,
+        config: RendererConfig,
+    ) -> Result<VkInterface, Box<dyn std::error::Error>> {
+        //before anything else, shader errors are the most likely to be fixed and retried
+        let shaders = ShaderSet::load(&config.shader_directory)?;
         let entry = unsafe { Entry::load()? };
         //TODO - requires validation layers to be installed on your machine
         let layer_names = vec![std::ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
-        let instance = init_instance(&entry, &layer_names)?;
+        let instance = init_instance(&entry, &layer_names, window.is_some())?;
         let debug = Debug::init(&entry, &instance)?;
-        let surface = Surface::init(&window, &entry, &instance)?;
-        //grab my discrete GPU, which I know has all the features I need
-        let (physical_device, physical_device_properties) =
-            get_physical_device_and_properties(&instance).unwrap();
-        let queue_families = QueueFamilies::init(&instance, physical_device)?;
-        let (device, queues) =
-            init_device_and_queues(&instance, physical_device, &queue_families, &layer_names)?;
+        let surface = match &window {
+            Some(window) => Some(Surface::init(window, &entry, &instance)?),
+            None => None,
+        };
+        let mut device_extensions = vec![];
+        if surface.is_some() {
+            device_extensions.push(ash::extensions::khr::Swapchain::name());
+        }
+        let required_features = vk::PhysicalDeviceFeatures::default();
+        let device_choice = config.device_choice.or_else(DeviceChoice::from_env);
+        let (physical_device, physical_device_properties) = get_physical_device_and_properties(
+            &instance,
+            surface.as_ref(),
+            &device_extensions,
+            &required_features,
+            device_choice.as_ref(),
+        )?;
+        //wireframes and points bigger than a pixel need them, but nothing else does
+        let available_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let enabled_features = vk::PhysicalDeviceFeatures {
+            fill_mode_non_solid: available_features.fill_mode_non_solid,
+            large_points: available_features.large_points,
+            ..required_features
+        };
+        let queue_families = QueueFamilies::init(&instance, physical_device, surface.as_ref())?;
+        let (device, queues) = init_device_and_queues(
+            &instance,
+            physical_device,
+            &queue_families,
+            &layer_names,
+            &device_extensions,
+            &enabled_features,
+        )?;
         let allocator_create_info = vk_mem::AllocatorCreateInfo::new(
             std::rc::Rc::new(&instance),
             std::rc::Rc::new(&device),
             physical_device,
         );
         let allocator = vk_mem::Allocator::new(allocator_create_info)?;
-        let mut swapchain = Swapchain::init(
-            &instance,
-            physical_device,
+        let (swapchain, offscreen, renderpass) = match &surface {
+            Some(surface) => {
+                let mut swapchain = Swapchain::init(
+                    &instance,
+                    physical_device,
+                    &device,
+                    surface,
+                    &queue_families,
+                    &allocator,
+                    extent,
+                    vk::SwapchainKHR::null(),
+                )?;
+                let renderpass = init_renderpass(
+                    &device,
+                    swapchain.surface_format.format,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                )?;
+                swapchain.create_framebuffers(&device, renderpass)?;
+                (Some(swapchain), None, renderpass)
+            }
+            None => {
+                let mut offscreen = Offscreen::init(&device, &queue_families, &allocator, extent)?;
+                let renderpass = init_renderpass(
+                    &device,
+                    Offscreen::FORMAT,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                )?;
+                offscreen.create_framebuffer(&device, renderpass)?;
+                (None, Some(offscreen), renderpass)
+            }
+        };
+        let pipeline_cache =
+            PipelineCache::init(&device, &physical_device_properties, config.pipeline_cache)?;
+        let pipeline = Pipeline::init(
             &device,
-            &surface,
-            &queue_families,
-            &allocator,
+            &renderpass,
+            pipeline_cache.cache,
+            &shaders,
+            &Model::<VertexData, InstanceData>::buffer_layouts(),
+            &PipelineState::default(),
         )?;
-        let renderpass = init_renderpass(&device, swapchain.surface_format.format)?;
-        swapchain.create_framebuffers(&device, renderpass)?;
-        let pipeline = Pipeline::init(&device, &swapchain, &renderpass)?;
+        let graphics_family = queue_families.graphics_q_index.unwrap() as usize;
+        let can_compute = unsafe {
+            instance.get_physical_device_queue_family_properties(physical_device)[graphics_family]
+                .queue_flags
+                .contains(vk::QueueFlags::COMPUTE)
+        };
+        if config.gpu_culling && !can_compute {
+            eprintln!("the graphics queue cannot run compute shaders, culling on the CPU instead");
+        }
+        let culling_pipeline = if config.gpu_culling && can_compute {
+            let code = crate::shaders::compile(config.shader_directory.join("culling/cull.comp"))?;
+            Some(ComputePipeline::init(&device, pipeline_cache.cache, &code)?)
+        } else {
+            None
+        };
         let pools = Pools::init(&device, &queue_families)?;
-        let command_buffers =
-            create_commandbuffers(&device, &pools, swapchain.amount_of_images as usize)?;
-
-        let mut uniformbuffer = Buffer::new(
-            &allocator,
-            128,
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
-            vk_mem::MemoryUsage::CpuToGpu,
-        )?;
-        let cameratransform: [[[f32; 4]; 4]; 2] = [
-            na::Matrix4::identity().into(),
-            na::Matrix4::identity().into(),
-        ];
-        unsafe { uniformbuffer.fill(&allocator, &cameratransform)? };
-        let pool_sizes = [vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: swapchain.amount_of_images,
-        }];
-        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets(swapchain.amount_of_images)
-            .pool_sizes(&pool_sizes);
-        let descriptor_pool =
-            unsafe { device.create_descriptor_pool(&descriptor_pool_info, None) }?;
-
-        let desc_layouts =
-            vec![pipeline.descriptor_set_layouts[0]; swapchain.amount_of_images as usize];
-        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&desc_layouts);
-        let descriptor_sets =
-            unsafe { device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
+        let uploader = Uploader::init(
+            &device,
+            &pools,
+            queues.transfer_queue,
+            queue_families.transfer_q_index.unwrap(),
+            queue_families.graphics_q_index.unwrap(),
+        );
 
-        for (_i, descset) in descriptor_sets.iter().enumerate() {
-            let buffer_infos = [vk::DescriptorBufferInfo {
-                buffer: uniformbuffer.buffer,
-                offset: 0,
-                range: 128,
-            }];
-            let desc_sets_write = [vk::WriteDescriptorSet::builder()
-                .dst_set(*descset)
-                .dst_binding(0)
-                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .buffer_info(&buffer_infos)
-                .build()];
-            unsafe { device.update_descriptor_sets(&desc_sets_write, &[]) };
+        let amount_of_frames = config.frames_in_flight.max(1);
+        let descriptor_pool = init_descriptor_pool(&device, amount_of_frames as u32)?;
+        let mut frames = Vec::with_capacity(amount_of_frames);
+        for _ in 0..amount_of_frames {
+            let mut frame = Frame::init(
+                &device,
+                &pools,
+                &allocator,
+                descriptor_pool,
+                pipeline.descriptor_set_layouts[0],
+            )?;
+            Camera::default().update_buffer(&allocator, &mut frame.uniformbuffer)?;
+            frames.push(frame);
         }
 
         Ok(VkInterface {
@@ -121,35 +248,347 @@ impl VkInterface {
             entry,
             instance,
             debug: std::mem::ManuallyDrop::new(debug),
-            surface: std::mem::ManuallyDrop::new(surface),
+            surface,
             physical_device,
             physical_device_properties,
             queue_families,
             device,
             queues,
             swapchain,
+            offscreen,
             renderpass,
-            pipeline,
+            pipeline_cache,
+            pipelines: vec![(None, pipeline)],
+            culling_pipeline,
+            failed_pipelines: vec![],
+            enabled_features,
             pools,
-            command_buffers,
+            uploader,
             allocator: std::mem::ManuallyDrop::new(allocator),
             models: vec![],
-            uniformbuffer,
+            model_groups: vec![],
             descriptor_pool,
-            descriptor_sets,
+            frames,
+            current_frame: 0,
+            clear_colour: [0.0, 0.0, 0.08, 1.0],
+            shader_directory: config.shader_directory,
         })
     }
-    pub(crate) fn update_commandbuffer(&mut self, index: usize) -> Result<(), vk::Result> {
-        let commandbuffer = self.command_buffers[index];
+    pub(crate) fn shader_directory(&self) -> &std::path::Path {
+        &self.shader_directory
+    }
+    //compiles the shaders again and switches to pipelines built from them; where they do not
+    //compile the current pipeline stays in use
+    pub(crate) fn reload_shaders(&mut self) -> Result<(), PipelineError> {
+        let directories: Vec<std::path::PathBuf> = self
+            .pipelines
+            .iter()
+            .map(|(group, pipeline)| {
+                self.shader_directory_for(*group, &pipeline.state)
+                    .to_path_buf()
+            })
+            .collect();
+        let rebuilt: Vec<Result<vk::Pipeline, PipelineError>> = self
+            .pipelines
+            .iter_mut()
+            .zip(directories)
+            .map(|((_, pipeline), directory)| {
+                let shaders = ShaderSet::load(&directory)?;
+                pipeline.replace_shaders(
+                    &self.device,
+                    &self.renderpass,
+                    self.pipeline_cache.cache,
+                    &shaders,
+                )
+            })
+            .collect();
+        self.failed_pipelines.clear();
+        //frames in flight may still be drawing with the old ones
+        unsafe { self.device.device_wait_idle()? };
+        let mut first_error = None;
+        for result in rebuilt {
+            match result {
+                Ok(old) => unsafe { self.device.destroy_pipeline(old, None) },
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+    //builds the pipelines of models whose state is new; models without a pipeline are not drawn.
+    //Reports the first pipeline that could not be built, which is not tried again
+    pub(crate) fn prepare_pipelines(&mut self) -> Result<(), PipelineError> {
+        let mut missing: Vec<(Option<usize>, PipelineState)> = vec![];
+        let states = self
+            .models
+            .iter()
+            .map(|m| (None, m.pipeline_state()))
+            .chain(self.model_groups.iter().enumerate().flat_map(|(g, group)| {
+                (0..group.len()).map(move |m| (Some(g), group.pipeline_state(m)))
+            }));
+        for (group, state) in states {
+            let known = |(g, s): &(Option<usize>, PipelineState)| *g == group && s == state;
+            if self.pipeline_index(group, state).is_none()
+                && !self.failed_pipelines.iter().any(known)
+                && !missing.iter().any(known)
+            {
+                missing.push((group, state.clone()));
+            }
+        }
+        let mut first_error = None;
+        for (group, state) in missing {
+            match self.build_pipeline(group, &state) {
+                Ok(pipeline) => self.pipelines.push((group, pipeline)),
+                Err(e) => {
+                    self.failed_pipelines.push((group, state));
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+    fn pipeline_index(&self, group: Option<usize>, state: &PipelineState) -> Option<usize> {
+        self.pipelines
+            .iter()
+            .position(|(g, pipeline)| *g == group && pipeline.state == *state)
+    }
+    fn shader_directory_for<'a>(
+        &'a self,
+        group: Option<usize>,
+        state: &'a PipelineState,
+    ) -> &'a std::path::Path {
+        match (&state.shaders, group) {
+            (Some(directory), _) => directory,
+            (None, Some(group)) => self.model_groups[group].shader_directory(),
+            (None, None) => &self.shader_directory,
+        }
+    }
+    fn build_pipeline(
+        &self,
+        group: Option<usize>,
+        state: &PipelineState,
+    ) -> Result<Pipeline, PipelineError> {
+        if state.polygon_mode != vk::PolygonMode::FILL
+            && self.enabled_features.fill_mode_non_solid == vk::FALSE
+        {
+            return Err(PipelineError::MissingFeature("fillModeNonSolid"));
+        }
+        let shaders = ShaderSet::load(self.shader_directory_for(group, state))?;
+        let buffers = match group {
+            Some(group) => self.model_groups[group].buffer_layouts(),
+            None => Model::<VertexData, InstanceData>::buffer_layouts(),
+        };
+        Pipeline::init_sharing_resources(
+            &self.device,
+            &self.renderpass,
+            self.pipeline_cache.cache,
+            &shaders,
+            &buffers,
+            state,
+            &self.pipelines[0].1,
+        )
+    }
+    //adds an empty group for models of other vertex or instance types, drawn with the shaders in
+    //`shader_directory`; those may only use descriptors the main shaders use as well
+    pub(crate) fn add_model_group<V: VertexLayout + Pod, I: VertexLayout + Pod>(
+        &mut self,
+        shader_directory: std::path::PathBuf,
+    ) -> Result<usize, PipelineError> {
+        self.model_groups
+            .push(Box::new(ModelGroup::<V, I>::new(shader_directory)));
+        let group = self.model_groups.len() - 1;
+        //built right away, so broken shaders are reported here
+        match self.build_pipeline(Some(group), &PipelineState::default()) {
+            Ok(pipeline) => {
+                self.pipelines.push((Some(group), pipeline));
+                Ok(group)
+            }
+            Err(e) => {
+                self.model_groups.pop();
+                Err(e)
+            }
+        }
+    }
+    //None if the group holds other types
+    pub(crate) fn model_group<V: 'static, I: 'static>(
+        &mut self,
+        index: usize,
+    ) -> Option<&mut ModelGroup<V, I>> {
+        self.model_groups
+            .get_mut(index)?
+            .as_any_mut()
+            .downcast_mut()
+    }
+    //uploads the vertices and indices of every model in the group, like update_vertexbuffers
+    pub(crate) fn update_model_group_vertexbuffers(
+        &mut self,
+        index: usize,
+    ) -> Result<(), BufferError> {
+        let frame = &mut self.frames[self.current_frame];
+        self.model_groups[index].update_vertexbuffers(
+            &self.allocator,
+            &mut self.uploader,
+            &mut frame.retired_buffers,
+        )
+    }
+    //returns false if the window is minimised, in which case nothing is rebuilt and drawing should pause
+    pub(crate) fn recreate_swapchain(&mut self) -> Result<bool, vk::Result> {
+        let (Some(window), Some(surface), Some(old_swapchain)) =
+            (&self.window, &self.surface, &mut self.swapchain)
+        else {
+            return Ok(false);
+        };
+        let surface_capabilities = surface.get_capabilities(self.physical_device)?;
+        let window_size = window.inner_size();
+        if window_size.width == 0
+            || window_size.height == 0
+            || surface_capabilities.current_extent.width == 0
+            || surface_capabilities.current_extent.height == 0
+        {
+            return Ok(false);
+        }
+        unsafe { self.device.device_wait_idle()? };
+        let mut swapchain = Swapchain::init(
+            &self.instance,
+            self.physical_device,
+            &self.device,
+            surface,
+            &self.queue_families,
+            &self.allocator,
+            vk::Extent2D {
+                width: window_size.width,
+                height: window_size.height,
+            },
+            old_swapchain.swapchain,
+        )?;
+        swapchain.create_framebuffers(&self.device, self.renderpass)?;
+        unsafe { old_swapchain.cleanup(&self.device, &self.allocator) };
+        self.swapchain = Some(swapchain);
+        Ok(true)
+    }
+    //uploads the visible instances of every model that `frustum` may see, and returns how many
+    //were culled; model groups upload all their visible instances. Buffers that had to grow are
+    //kept alive until the current frame has finished on the GPU. With GPU culling the models hand
+    //all their visible instances to the compute shader, and none count as culled here
+    pub(crate) fn update_instancebuffers(
+        &mut self,
+        frustum: &Frustum,
+    ) -> Result<usize, BufferError> {
+        let frame = &mut self.frames[self.current_frame];
+        let mut culled = 0;
+        for m in &mut self.models {
+            if self.culling_pipeline.is_some() {
+                m.update_gpu_instances(&self.allocator, frustum)?;
+            } else {
+                culled +=
+                    m.update_instancebuffer_culled(&self.allocator, self.current_frame, frustum)?;
+            }
+            frame.retired_buffers.extend(m.drain_retired_buffers());
+        }
+        for group in &mut self.model_groups {
+            group.update_instancebuffers(
+                &self.allocator,
+                self.current_frame,
+                &mut frame.retired_buffers,
+            )?;
+        }
+        Ok(culled)
+    }
+    //uploads the vertices and indices of the given models, like update_instancebuffers
+    pub(crate) fn update_vertexbuffers(&mut self, models: &[usize]) -> Result<(), BufferError> {
+        let frame = &mut self.frames[self.current_frame];
+        for &index in models {
+            let m = &mut self.models[index];
+            m.update_vertexbuffer(&self.allocator, &mut self.uploader)?;
+            frame.retired_buffers.extend(m.drain_retired_buffers());
+        }
+        Ok(())
+    }
+    //swaps the models in `range` for `models`, uploading the new ones and retiring the buffers of
+    //the old ones
+    pub(crate) fn replace_models(
+        &mut self,
+        range: std::ops::Range<usize>,
+        mut models: Vec<Model<VertexData, InstanceData>>,
+    ) -> Result<(), BufferError> {
+        let frame = &mut self.frames[self.current_frame];
+        for m in &mut models {
+            m.update_vertexbuffer(&self.allocator, &mut self.uploader)?;
+            m.update_instancebuffer(&self.allocator, self.current_frame)?;
+        }
+        for mut old in self.models.splice(range, models) {
+            old.retire_buffers();
+            frame.retired_buffers.extend(old.drain_retired_buffers());
+        }
+        Ok(())
+    }
+    //every model with a pipeline as (transparent, pipeline, group, model); opaque models come first so
+    //transparent ones can blend with them, and models sharing a pipeline follow each other
+    fn sorted_draws(&self) -> Vec<(bool, usize, Option<usize>, usize)> {
+        let mut draws = vec![];
+        for (m, model) in self.models.iter().enumerate() {
+            let state = model.pipeline_state();
+            if let Some(pipeline) = self.pipeline_index(None, state) {
+                draws.push((state.is_transparent(), pipeline, None, m));
+            }
+        }
+        for (g, group) in self.model_groups.iter().enumerate() {
+            for m in 0..group.len() {
+                let state = group.pipeline_state(m);
+                if let Some(pipeline) = self.pipeline_index(Some(g), state) {
+                    draws.push((state.is_transparent(), pipeline, Some(g), m));
+                }
+            }
+        }
+        draws.sort_by_key(|&(transparent, pipeline, _, _)| (transparent, pipeline));
+        draws
+    }
+    //records the current frame's command buffer to draw into the given swapchain image, after
+    //sending off the uploads since the last frame; the frame's submission has to wait on its
+    //upload_finished semaphore, if there is one
+    pub(crate) fn update_commandbuffer(&mut self, image_index: usize) -> Result<(), vk::Result> {
+        self.uploader.collect(&self.allocator)?;
+        let upload = self.uploader.submit()?;
+        let frame = &mut self.frames[self.current_frame];
+        //the frame's fence has been waited on, so the last submission waiting on it is done
+        if let Some(semaphore) = frame.upload_finished.take() {
+            self.uploader.recycle(semaphore);
+        }
+        frame.upload_finished = upload.as_ref().map(|upload| upload.finished);
+        let mut culled = vec![];
+        if let Some(culling_pipeline) = &self.culling_pipeline {
+            unsafe { frame.reset_culling_descriptor_sets(&self.device)? };
+            for gpu_instances in self.models.iter().filter_map(Model::gpu_instances) {
+                let set = frame.allocate_culling_descriptor_set(
+                    &self.device,
+                    culling_pipeline.descriptor_set_layouts[0],
+                )?;
+                culled.push((gpu_instances, set));
+            }
+        }
+        let frame = &self.frames[self.current_frame];
+        let commandbuffer = frame.commandbuffer;
+        let (framebuffer, extent) = match (&self.swapchain, &self.offscreen) {
+            (Some(swapchain), _) => (swapchain.framebuffers[image_index], swapchain.extent),
+            (None, Some(offscreen)) => (offscreen.framebuffer, offscreen.extent),
+            (None, None) => unreachable!("VkInterface always has a render target"),
+        };
         let commandbuffer_begininfo = vk::CommandBufferBeginInfo::builder();
         unsafe {
             self.device
                 .begin_command_buffer(commandbuffer, &commandbuffer_begininfo)?;
+            if let Some(upload) = &upload {
+                upload.record_acquires(&self.device, commandbuffer);
+            }
+            if let Some(culling_pipeline) = &self.culling_pipeline {
+                gpuculling::record(&self.device, commandbuffer, culling_pipeline, &culled);
+            }
         }
         let clearvalues = [
             vk::ClearValue {
                 color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.08, 1.0],
+                    float32: self.clear_colour,
                 },
             },
             vk::ClearValue {
@@ -161,10 +600,10 @@ impl VkInterface {
         ];
         let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
             .render_pass(self.renderpass)
-            .framebuffer(self.swapchain.framebuffers[index])
+            .framebuffer(framebuffer)
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.swapchain.extent,
+                extent,
             })
             .clear_values(&clearvalues);
         unsafe {
@@ -173,30 +612,104 @@ impl VkInterface {
                 &renderpass_begininfo,
                 vk::SubpassContents::INLINE,
             );
-            self.device.cmd_bind_pipeline(
+            self.device.cmd_set_viewport(
                 commandbuffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline.pipeline,
+                0,
+                &[vk::Viewport {
+                    x: 0.,
+                    y: 0.,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.,
+                    max_depth: 1.,
+                }],
+            );
+            self.device.cmd_set_scissor(
+                commandbuffer,
+                0,
+                &[vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                }],
             );
             self.device.cmd_bind_descriptor_sets(
                 commandbuffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline.layout,
+                self.pipelines[0].1.layout,
                 0,
-                &[self.descriptor_sets[index]],
+                &[frame.descriptor_set],
                 &[],
             );
-            for m in &self.models {
-                m.draw(&self.device, commandbuffer);
+            //the descriptor set stays bound, all pipelines share the layouts of the first one
+            let mut bound = None;
+            for (_, pipeline, group, model) in self.sorted_draws() {
+                if bound != Some(pipeline) {
+                    self.device.cmd_bind_pipeline(
+                        commandbuffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipelines[pipeline].1.pipeline,
+                    );
+                    bound = Some(pipeline);
+                }
+                match group {
+                    Some(group) => self.model_groups[group].draw(
+                        model,
+                        &self.device,
+                        commandbuffer,
+                        self.current_frame,
+                    ),
+                    None => {
+                        self.models[model].draw(&self.device, commandbuffer, self.current_frame)
+                    }
+                }
             }
             self.device.cmd_end_render_pass(commandbuffer);
+            if let Some(offscreen) = &self.offscreen {
+                offscreen.record_readback(&self.device, commandbuffer);
+            }
             self.device.end_command_buffer(commandbuffer)?;
         }
         Ok(())
     }
+    //draws the models once into the offscreen target and returns the image as RGBA8 rows; fails if
+    //a model's pipeline cannot be built
+    pub(crate) fn render_offscreen(&mut self) -> Result<Vec<u8>, PipelineError> {
+        self.prepare_pipelines()?;
+        let may_begin_drawing = self.frames[self.current_frame].may_begin_drawing;
+        unsafe {
+            self.device
+                .wait_for_fences(&[may_begin_drawing], true, u64::MAX)?;
+            self.device.reset_fences(&[may_begin_drawing])?;
+        }
+        self.update_commandbuffer(0)?;
+        let offscreen = self
+            .offscreen
+            .as_mut()
+            .expect("render_offscreen needs a headless VkInterface");
+        let frame = &self.frames[self.current_frame];
+        let commandbuffers = [frame.commandbuffer];
+        let semaphores_available: Vec<_> = frame.upload_finished.into_iter().collect();
+        let waiting_stages =
+            [vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::COMPUTE_SHADER];
+        let submit_info = [vk::SubmitInfo::builder()
+            .wait_semaphores(&semaphores_available)
+            .wait_dst_stage_mask(&waiting_stages[..semaphores_available.len()])
+            .command_buffers(&commandbuffers)
+            .build()];
+        unsafe {
+            self.device.queue_submit(
+                self.queues.graphics_queue,
+                &submit_info,
+                may_begin_drawing,
+            )?;
+            self.device
+                .wait_for_fences(&[may_begin_drawing], true, u64::MAX)?;
+            let bytes = offscreen.extent.width as usize * offscreen.extent.height as usize * 4;
+            Ok(offscreen.readback.read(&self.allocator, bytes)?)
+        }
+    }
 }
 
-//TODO - a semaphore isn't dropped?, also allocs being horrible
 impl Drop for VkInterface {
     fn drop(&mut self) {
         unsafe {
@@ -204,31 +717,45 @@ impl Drop for VkInterface {
                 .device_wait_idle()
                 .expect("something wrong while waiting");
 
+            //nothing is in flight any more, so the buffers of every model can go right away
+            let mut retired = vec![];
+            for m in &mut self.models {
+                m.retire_buffers();
+                retired.extend(m.drain_retired_buffers());
+            }
+            for group in &mut self.model_groups {
+                group.retire_buffers(&mut retired);
+            }
+            for mut buffer in retired {
+                buffer.destroy(&self.allocator);
+            }
+            for frame in &mut self.frames {
+                frame.cleanup(&self.device, &self.allocator);
+            }
             self.device
                 .destroy_descriptor_pool(self.descriptor_pool, None);
-            /*
-            self.allocator
-                .destroy_buffer(self.uniformbuffer.buffer, self.uniformbuffer.allocation);
-            for m in self.models {
-                if let Some(vb) = &m.vertexbuffer {
-                    self.allocator
-                        .destroy_buffer(vb.buffer, vb.allocation)
-                        .expect("problem with buffer destruction");
-                }
-                if let Some(ib) = &m.instancebuffer {
-                    self.allocator
-                        .destroy_buffer(ib.buffer, ib.allocation)
-                        .expect("problem with buffer destruction");
-                }
-            }*/
-
+            self.uploader.cleanup(&self.allocator);
             self.pools.cleanup(&self.device);
-            self.pipeline.cleanup(&self.device);
+            if let Err(e) = self.pipeline_cache.save(&self.device) {
+                eprintln!("{}", e);
+            }
+            for (_, pipeline) in &self.pipelines {
+                pipeline.cleanup(&self.device);
+            }
+            if let Some(culling_pipeline) = &self.culling_pipeline {
+                culling_pipeline.cleanup(&self.device);
+            }
+            self.pipeline_cache.cleanup(&self.device);
             self.device.destroy_render_pass(self.renderpass, None);
-            self.swapchain.cleanup(&self.device /*&self.allocator*/);
+            if let Some(swapchain) = &mut self.swapchain {
+                swapchain.cleanup(&self.device, &self.allocator);
+            }
+            if let Some(offscreen) = &mut self.offscreen {
+                offscreen.cleanup(&self.device, &self.allocator);
+            }
             std::mem::ManuallyDrop::drop(&mut self.allocator);
             self.device.destroy_device(None);
-            std::mem::ManuallyDrop::drop(&mut self.surface);
+            self.surface = None;
             std::mem::ManuallyDrop::drop(&mut self.debug);
             self.instance.destroy_instance(None)
         };
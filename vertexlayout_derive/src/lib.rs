@@ -0,0 +1,81 @@
+//#[derive(VertexLayout)] for the vertex and instance structs of the graphics crate: every field becomes
+//the attributes of its type, at the field's offset, in the order of declaration
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+#[proc_macro_derive(VertexLayout)]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    //without it the compiler may reorder the fields, and the offsets would mean nothing to the GPU
+    if !is_repr_c(input) {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "VertexLayout needs #[repr(C)]",
+        ));
+    }
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "VertexLayout can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "VertexLayout needs named fields",
+        ));
+    };
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let fields = fields.named.iter().map(|field| {
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let ty = &field.ty;
+        quote! {
+            attributes.extend(
+                <#ty as crate::vertexlayout::AttributeType>::ATTRIBUTES
+                    .iter()
+                    .map(|attribute| crate::vertexlayout::VertexAttribute {
+                        offset: attribute.offset + ::std::mem::offset_of!(Self, #ident) as u32,
+                        format: attribute.format,
+                    }),
+            );
+        }
+    });
+    Ok(quote! {
+        impl #impl_generics crate::vertexlayout::VertexLayout for #name #type_generics #where_clause {
+            fn attributes() -> ::std::vec::Vec<crate::vertexlayout::VertexAttribute> {
+                let mut attributes = ::std::vec::Vec::new();
+                #(#fields)*
+                attributes
+            }
+        }
+    })
+}
+
+fn is_repr_c(input: &DeriveInput) -> bool {
+    input
+        .attrs
+        .iter()
+        .filter(|attribute| attribute.path().is_ident("repr"))
+        .any(|attribute| {
+            let mut c = false;
+            //repr(C, align(16)) and the like count as well
+            let _ = attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("C") {
+                    c = true;
+                } else if meta.input.peek(syn::token::Paren) {
+                    let _ = meta.input.parse::<proc_macro2::Group>();
+                }
+                Ok(())
+            });
+            c
+        })
+}